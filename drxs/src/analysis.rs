@@ -0,0 +1,128 @@
+use std::{collections::BTreeMap, fmt};
+
+use cube_lib::cube333::CubieCube;
+use rand::{Rng, seq::IteratorRandom};
+
+use crate::{DrxsTarget, LinearSolver, MoveSet, prune::MOVES};
+
+/// The number of random moves used to make a random state. This is far more than is needed to
+/// reach every state, so the states are close enough to uniformly random.
+const RANDOM_MOVES: usize = 100;
+
+/// The widest a bar of a histogram is drawn.
+const BAR_WIDTH: usize = 50;
+
+/// A random state reachable with the moves of `set`. With `MoveSet::eo(a)`, this is a random state
+/// with the edges oriented for axis `a`.
+pub fn random_state(rng: &mut impl Rng, set: MoveSet) -> CubieCube {
+    let mut cube = CubieCube::SOLVED;
+    let mut prev = None;
+    for _ in 0..RANDOM_MOVES {
+        let Some(m) = MOVES
+            .into_iter()
+            .filter(|&m| set.contains(m) && Some(m.ty) != prev)
+            .choose(rng)
+        else {
+            break;
+        };
+        cube = cube.make_move(m);
+        prev = Some(m.ty);
+    }
+    cube
+}
+
+/// The length of the shortest solution from `cube` to a dr-xs described by `target`, if there is
+/// one of at most `max_depth` moves.
+pub fn optimal_length(cube: &CubieCube, target: &DrxsTarget, max_depth: usize) -> Option<usize> {
+    let mut solver = LinearSolver::new(cube.clone());
+    solver.set_max_depth(max_depth);
+    solver.set_target(target.clone());
+    solver.next().map(|sol| sol.0.len())
+}
+
+/// How many states had their optimal solutions of each length.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Histogram {
+    pub lengths: BTreeMap<usize, usize>,
+    /// The number of states with no solution short enough to be found.
+    pub unsolved: usize,
+}
+
+impl Histogram {
+    pub fn new() -> Histogram {
+        Histogram::default()
+    }
+
+    /// Counts a state whose optimal solution has length `len`, or which wasn't solved.
+    pub fn add(&mut self, len: Option<usize>) {
+        match len {
+            Some(len) => *self.lengths.entry(len).or_default() += 1,
+            None => self.unsolved += 1,
+        }
+    }
+
+    /// The number of states which were solved.
+    pub fn solved(&self) -> usize {
+        self.lengths.values().sum()
+    }
+
+    /// The mean length of the solved states.
+    pub fn mean(&self) -> Option<f64> {
+        let solved = self.solved();
+        (solved > 0).then(|| {
+            self.lengths.iter().map(|(len, n)| len * n).sum::<usize>() as f64 / solved as f64
+        })
+    }
+}
+
+/// One line per length, with a bar as long as its count relative to the most common length.
+impl fmt::Display for Histogram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let most = self.lengths.values().copied().max().unwrap_or(0);
+        for (len, &n) in &self.lengths {
+            let bar = "#".repeat((n * BAR_WIDTH).div_ceil(most));
+            writeln!(f, "{len:>3} | {bar} {n}")?;
+        }
+        if self.unsolved > 0 {
+            writeln!(f, "  - | unsolved {}", self.unsolved)?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn random_states() {
+    use cube_lib::cube333::{axis::Axis, edge::EdgeFlip};
+    use rand::{SeedableRng, rngs::StdRng};
+
+    let states = |seed| {
+        let mut rng = StdRng::seed_from_u64(seed);
+        (0..5)
+            .map(|_| random_state(&mut rng, MoveSet::eo(Axis::UD)))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(states(1), states(1));
+    assert_ne!(states(1), states(2));
+    for cube in states(3) {
+        assert!(
+            cube.axis_eo(Axis::UD)
+                .iter()
+                .all(|&f| f == EdgeFlip::Oriented)
+        );
+    }
+
+    let mut histogram = Histogram::new();
+    for len in [Some(3), Some(5), Some(5), None] {
+        histogram.add(len);
+    }
+    assert_eq!(histogram.solved(), 3);
+    assert_eq!(histogram.mean(), Some(13.0 / 3.0));
+    assert_eq!(
+        histogram.to_string(),
+        format!(
+            "  3 | {} 1\n  5 | {} 2\n  - | unsolved 1\n",
+            "#".repeat(25),
+            "#".repeat(50)
+        )
+    );
+}
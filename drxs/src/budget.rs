@@ -0,0 +1,104 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+/// The number of nodes a solver visits between looking at the clock, which is slow next to a move.
+const CLOCK_INTERVAL: u64 = 1024;
+
+/// Stops the searches it was given to, from any thread. Clones share the same flag.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// How much a search may do before it gives up. A node is a sequence of moves that the search
+/// visits. Clones share the nodes left, so a budget handed to several solvers is split between
+/// them.
+#[derive(Debug, Clone, Default)]
+pub struct Budget {
+    /// The number of nodes left, if they are limited.
+    nodes: Option<Arc<AtomicU64>>,
+    deadline: Option<Instant>,
+    cancel: Option<CancelToken>,
+}
+
+impl Budget {
+    /// A budget without any limits.
+    pub fn new() -> Budget {
+        Budget::default()
+    }
+
+    /// Stop after visiting `n` nodes.
+    pub fn nodes(mut self, n: u64) -> Self {
+        self.nodes = Some(Arc::new(AtomicU64::new(n)));
+        self
+    }
+
+    /// Stop once `deadline` has passed.
+    pub fn deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Stop once `time` has passed from now.
+    pub fn time_limit(self, time: Duration) -> Self {
+        self.deadline(Instant::now() + time)
+    }
+
+    /// Stop once `token` is cancelled.
+    pub fn cancel_token(mut self, token: CancelToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+
+    /// Uses up a node, returning whether the search may visit it. `visited` is how many nodes the
+    /// solver has visited before, so that the clock is only looked at every so often.
+    pub(crate) fn spend(&self, visited: u64) -> bool {
+        if self.cancel.as_ref().is_some_and(CancelToken::is_cancelled) {
+            return false;
+        }
+        if visited.is_multiple_of(CLOCK_INTERVAL)
+            && self.deadline.is_some_and(|d| Instant::now() >= d)
+        {
+            return false;
+        }
+        self.nodes.as_ref().is_none_or(|nodes| {
+            nodes
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1))
+                .is_ok()
+        })
+    }
+}
+
+#[test]
+fn budgets() {
+    let budget = Budget::new().nodes(3);
+    let shared = budget.clone();
+    assert!(budget.spend(0) && shared.spend(0) && budget.spend(1));
+    assert!(!shared.spend(1));
+
+    let token = CancelToken::new();
+    let budget = Budget::new().cancel_token(token.clone());
+    assert!(budget.spend(0));
+    token.clone().cancel();
+    assert!(!budget.spend(1));
+
+    assert!(!Budget::new().deadline(Instant::now()).spend(0));
+    assert!(Budget::new().time_limit(Duration::from_secs(60)).spend(0));
+}
@@ -0,0 +1,620 @@
+use std::{collections::VecDeque, fmt};
+
+use cube_lib::{
+    cube333::{CubieCube, moves::Move333},
+    moves::{MoveSequence, NissSequence},
+};
+
+use crate::{
+    Budget, DrxsTarget, LinearSolver, MoveSet, NissSolver, last_variation,
+    parallel::ParallelSolver, pipeline::cancels, quarter_turn_variants, search_key,
+    symmetry::SymmetryFilter,
+};
+
+/// Configures a dr-xs search. Once built, the search can't be changed, so everything has to be
+/// set up here first.
+#[derive(Debug, Clone)]
+pub struct LinearSolverBuilder {
+    min_depth: usize,
+    max_depth: usize,
+    limit: Option<usize>,
+    target: DrxsTarget,
+    move_set: MoveSet,
+    niss: bool,
+    parallel: bool,
+    variants: bool,
+    reduce_symmetry: bool,
+    budget: Budget,
+    prefix: NissSequence<Move333>,
+}
+
+/// The reasons a `LinearSolverBuilder` can refuse to build a search.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BuildError {
+    /// The minimum depth is above the maximum depth.
+    MinAboveMax { min: usize, max: usize },
+    /// The target asks for more than four slice edges to be outside of the slice.
+    BadSliceCount(usize),
+    /// The target doesn't allow any axes.
+    NoAxes,
+    /// The target doesn't allow any corner orientations.
+    NoCornerPatterns,
+    /// The move set doesn't have any moves in it.
+    EmptyMoveSet,
+    /// The cube to carry on from isn't in a dr-xs, see `FinishBuilder`.
+    NotDrxs,
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::MinAboveMax { min, max } => write!(
+                f,
+                "the minimum length ({min}) is greater than the maximum length ({max})"
+            ),
+            BuildError::BadSliceCount(n) => {
+                write!(
+                    f,
+                    "there are only 4 slice edges, so {n} can't be out of the slice"
+                )
+            }
+            BuildError::NoAxes => write!(f, "the target doesn't allow any axes"),
+            BuildError::NoCornerPatterns => {
+                write!(f, "the target doesn't allow any corner orientations")
+            }
+            BuildError::EmptyMoveSet => write!(f, "the move set is empty"),
+            BuildError::NotDrxs => write!(f, "the cube isn't in a dr-xs"),
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+/// Checks that a target describes at least one dr-xs state.
+pub(crate) fn check_target(target: &DrxsTarget) -> Result<(), BuildError> {
+    if target.bad_slice > 4 {
+        return Err(BuildError::BadSliceCount(target.bad_slice));
+    }
+    if target.axes.is_empty() {
+        return Err(BuildError::NoAxes);
+    }
+    if target.corners.is_empty() {
+        return Err(BuildError::NoCornerPatterns);
+    }
+    Ok(())
+}
+
+impl Default for LinearSolverBuilder {
+    fn default() -> Self {
+        LinearSolverBuilder {
+            min_depth: 0,
+            max_depth: 20,
+            limit: None,
+            target: DrxsTarget::default(),
+            move_set: MoveSet::HTM,
+            niss: false,
+            parallel: false,
+            variants: false,
+            reduce_symmetry: false,
+            budget: Budget::new(),
+            prefix: NissSequence {
+                normal: MoveSequence(vec![]),
+                inverse: MoveSequence(vec![]),
+            },
+        }
+    }
+}
+
+impl LinearSolverBuilder {
+    pub fn new() -> LinearSolverBuilder {
+        LinearSolverBuilder::default()
+    }
+
+    /// Don't return solutions shorter than `d` moves.
+    pub fn min_depth(mut self, d: usize) -> Self {
+        self.min_depth = d;
+        self
+    }
+
+    /// Don't return solutions longer than `d` moves.
+    pub fn max_depth(mut self, d: usize) -> Self {
+        self.max_depth = d;
+        self
+    }
+
+    /// Follow each solution with its variations that reverse the final quarter turns, see
+    /// `quarter_turn_variants`. These are skipped otherwise.
+    pub fn variants(mut self, variants: bool) -> Self {
+        self.variants = variants;
+        self
+    }
+
+    /// Stop after `n` solutions.
+    pub fn limit(mut self, n: usize) -> Self {
+        self.limit = Some(n);
+        self
+    }
+
+    /// Only look for the dr-xs states described by `target`.
+    pub fn target(mut self, target: DrxsTarget) -> Self {
+        self.target = target;
+        self
+    }
+
+    /// Only use the moves in `set`.
+    pub fn move_set(mut self, set: MoveSet) -> Self {
+        self.move_set = set;
+        self
+    }
+
+    /// Allow solutions to switch to the inverse part way through, see `NissSolver`.
+    pub fn niss(mut self, niss: bool) -> Self {
+        self.niss = niss;
+        self
+    }
+
+    /// Search on several threads. The solutions are the same, and come in the same order, but
+    /// they are returned a branch of the search at a time. Searches using niss always run on one
+    /// thread.
+    pub fn parallel(mut self, parallel: bool) -> Self {
+        self.parallel = parallel;
+        self
+    }
+
+    /// Skip the solutions which are the image of an earlier solution under a symmetry that leaves
+    /// the scrambled cube as it is, see `Symmetry::fixing`.
+    pub fn reduce_symmetry(mut self, reduce: bool) -> Self {
+        self.reduce_symmetry = reduce;
+        self
+    }
+
+    /// Give up once `budget` runs out, see `Solutions::out_of_budget`.
+    pub fn budget(mut self, budget: Budget) -> Self {
+        self.budget = budget;
+        self
+    }
+
+    /// Carry on from `prefix`, such as a skeleton found earlier, which is applied after the
+    /// scramble. Only the moves after the prefix are searched for, so the depths don't count the
+    /// prefix, but the solutions returned start with it. A continuation which starts on the face
+    /// the prefix ends with is skipped, since it could be written with fewer moves.
+    ///
+    /// If the prefix has inverse moves, the switch to the inverse has been made already, so the
+    /// search carries on linearly on the inverse and `niss` is ignored.
+    pub fn prefix(mut self, prefix: NissSequence<Move333>) -> Self {
+        self.prefix = prefix;
+        self
+    }
+
+    fn check(&self) -> Result<(), BuildError> {
+        if self.min_depth > self.max_depth {
+            return Err(BuildError::MinAboveMax {
+                min: self.min_depth,
+                max: self.max_depth,
+            });
+        }
+        check_target(&self.target)?;
+        if self.move_set.is_empty() {
+            return Err(BuildError::EmptyMoveSet);
+        }
+        Ok(())
+    }
+
+    /// Start searching for solutions to `scramble`.
+    pub fn build(self, scramble: NissSequence<Move333>) -> Result<Solutions, BuildError> {
+        self.check()?;
+
+        let prefix = Prefix(self.prefix);
+        let scramble = prefix.search_scramble(scramble);
+        let variants = self.variants.then(|| Variants {
+            scramble: scramble.clone(),
+            target: self.target.clone(),
+            move_set: self.move_set,
+            pending: VecDeque::new(),
+        });
+        let symmetries = self.reduce_symmetry.then(|| SymmetryFilter::new(&scramble));
+        // The budget is set before anything else, so that the setup of the search is counted too
+        let search = if self.niss && !prefix.on_inverse() {
+            let mut solver = NissSolver::new(scramble);
+            solver.set_budget(self.budget);
+            solver.set_max_depth(self.max_depth);
+            solver.set_min_depth(self.min_depth);
+            solver.set_target(self.target);
+            solver.set_move_set(self.move_set);
+            Search::Niss(Box::new(solver))
+        } else if self.parallel {
+            let mut solver = ParallelSolver::new(CubieCube::SOLVED.make_niss_moves(scramble));
+            solver.set_budget(self.budget);
+            solver.set_max_depth(self.max_depth);
+            solver.set_target(self.target);
+            solver.set_move_set(self.move_set);
+            solver.set_min_depth(self.min_depth);
+            Search::Parallel(solver)
+        } else {
+            let mut solver = LinearSolver::new(CubieCube::SOLVED.make_niss_moves(scramble));
+            solver.set_budget(self.budget);
+            solver.set_max_depth(self.max_depth);
+            solver.set_target(self.target);
+            solver.set_move_set(self.move_set);
+            solver.set_min_depth(self.min_depth);
+            Search::Linear(solver)
+        };
+
+        Ok(Solutions {
+            search,
+            remaining: self.limit,
+            variants,
+            symmetries,
+            prefix,
+        })
+    }
+}
+
+/// The moves that the solutions of a search carry on from, see `LinearSolverBuilder::prefix`.
+struct Prefix(NissSequence<Move333>);
+
+impl Prefix {
+    /// Whether the search carries on from the inverse side of the prefix, in which case the sides
+    /// of the scramble it searches are swapped.
+    fn on_inverse(&self) -> bool {
+        !self.0.inverse.0.is_empty()
+    }
+
+    /// The scramble that the search solves, which is `scramble` followed by the prefix.
+    fn search_scramble(&self, scramble: NissSequence<Move333>) -> NissSequence<Move333> {
+        let mut normal = scramble.normal;
+        normal.0.extend(self.0.normal.0.iter().copied());
+        let mut inverse = scramble.inverse;
+        inverse.0.extend(self.0.inverse.0.iter().copied());
+        if self.on_inverse() {
+            // Swapping the sides of a niss sequence inverts the cube it produces
+            NissSequence {
+                normal: inverse,
+                inverse: normal,
+            }
+        } else {
+            NissSequence { normal, inverse }
+        }
+    }
+
+    /// The prefix followed by a solution of the search, unless the solution cancels with it.
+    fn join(&self, sol: NissSequence<Move333>) -> Option<NissSequence<Move333>> {
+        let NissSequence { normal, inverse } = self.0.clone();
+        if self.on_inverse() {
+            // The search doesn't use niss, so all of its moves are on the inverse
+            if cancels(&inverse, &sol.normal) {
+                return None;
+            }
+            let mut inverse = inverse;
+            inverse.0.extend(sol.normal.0);
+            Some(NissSequence { normal, inverse })
+        } else {
+            if cancels(&normal, &sol.normal) {
+                return None;
+            }
+            let mut normal = normal;
+            normal.0.extend(sol.normal.0);
+            Some(NissSequence {
+                normal,
+                inverse: sol.inverse,
+            })
+        }
+    }
+}
+
+enum Search {
+    Linear(LinearSolver),
+    Niss(Box<NissSolver>),
+    Parallel(ParallelSolver),
+}
+
+/// The solutions of a search configured by a `LinearSolverBuilder`, in order of length. Linear
+/// solutions have an empty inverse.
+pub struct Solutions {
+    search: Search,
+    /// How many more solutions may be returned, if there is a limit.
+    remaining: Option<usize>,
+    variants: Option<Variants>,
+    symmetries: Option<SymmetryFilter>,
+    prefix: Prefix,
+}
+
+/// What is needed to find the quarter turn variations of a solution.
+struct Variants {
+    scramble: NissSequence<Move333>,
+    target: DrxsTarget,
+    move_set: MoveSet,
+    /// Variations of the last solution which haven't been returned yet.
+    pending: VecDeque<NissSequence<Move333>>,
+}
+
+impl Variants {
+    /// The variations of `sol` reached from `start` which the search skipped over, so the ones
+    /// that wouldn't be returned anyway.
+    fn skipped(
+        &self,
+        start: &CubieCube,
+        sol: &MoveSequence<Move333>,
+    ) -> Vec<MoveSequence<Move333>> {
+        let last = last_variation(&sol.0);
+        quarter_turn_variants(start, sol, &self.target)
+            .into_iter()
+            .filter(|v| {
+                v.0.iter().all(|&m| self.move_set.contains(m))
+                    && search_key(&v.0).gt(search_key(&sol.0))
+                    && last
+                        .as_ref()
+                        .is_none_or(|last| search_key(&v.0).le(search_key(last)))
+            })
+            .collect()
+    }
+
+    /// Queues up the variations of a solution, which change the moves on whichever side it
+    /// finishes on.
+    fn add(&mut self, sol: &NissSequence<Move333>) {
+        if sol.inverse.0.is_empty() {
+            let start = CubieCube::SOLVED.make_niss_moves(self.scramble.clone());
+            let variants = self.skipped(&start, &sol.normal);
+            self.pending
+                .extend(variants.into_iter().map(|normal| NissSequence {
+                    normal,
+                    inverse: MoveSequence(vec![]),
+                }));
+        } else {
+            let mut normal = self.scramble.normal.clone();
+            normal.0.extend(sol.normal.0.iter().copied());
+            // Swapping the sides of a niss sequence inverts the cube it produces
+            let start = CubieCube::SOLVED.make_niss_moves(NissSequence {
+                normal: self.scramble.inverse.clone(),
+                inverse: normal,
+            });
+            let variants = self.skipped(&start, &sol.inverse);
+            self.pending
+                .extend(variants.into_iter().map(|inverse| NissSequence {
+                    normal: sol.normal.clone(),
+                    inverse,
+                }));
+        }
+    }
+}
+
+impl Solutions {
+    /// Whether the search ended because its budget ran out, in which case some solutions may not
+    /// have been found.
+    pub fn out_of_budget(&self) -> bool {
+        match &self.search {
+            Search::Linear(solver) => solver.out_of_budget(),
+            Search::Niss(solver) => solver.out_of_budget(),
+            Search::Parallel(solver) => solver.out_of_budget(),
+        }
+    }
+
+    /// The longest solution length that has been searched all the way through, if any. Every
+    /// solution up to this length has been found, so the shortest of them are optimal.
+    pub fn finished_depth(&self) -> Option<usize> {
+        match &self.search {
+            Search::Linear(solver) => solver.finished_depth(),
+            Search::Niss(solver) => solver.finished_depth(),
+            Search::Parallel(solver) => solver.finished_depth(),
+        }
+    }
+
+    /// The next solution, before any are skipped for being symmetric.
+    fn next_solution(&mut self) -> Option<NissSequence<Move333>> {
+        if let Some(sol) = self.variants.as_mut().and_then(|v| v.pending.pop_front()) {
+            return Some(sol);
+        }
+
+        let sol = match &mut self.search {
+            Search::Linear(solver) => solver.next().map(|sol| NissSequence {
+                normal: sol,
+                inverse: MoveSequence(vec![]),
+            }),
+            Search::Niss(solver) => solver.next(),
+            Search::Parallel(solver) => solver.next().map(|sol| NissSequence {
+                normal: sol,
+                inverse: MoveSequence(vec![]),
+            }),
+        }?;
+        if let Some(v) = &mut self.variants {
+            v.add(&sol);
+        }
+        Some(sol)
+    }
+}
+
+impl Iterator for Solutions {
+    type Item = NissSequence<Move333>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(n) = &mut self.remaining {
+            *n = n.checked_sub(1)?;
+        }
+
+        loop {
+            let sol = self.next_solution()?;
+            if self.symmetries.as_mut().is_none_or(|s| s.is_new(&sol))
+                && let Some(sol) = self.prefix.join(sol)
+            {
+                return Some(sol);
+            }
+        }
+    }
+}
+
+#[test]
+fn invalid_configurations() {
+    let scramble: NissSequence<Move333> = "R U F".parse().unwrap();
+    let build = |b: LinearSolverBuilder| b.build(scramble.clone()).err();
+
+    assert_eq!(
+        build(LinearSolverBuilder::new().min_depth(5).max_depth(4)),
+        Some(BuildError::MinAboveMax { min: 5, max: 4 })
+    );
+    let target = |target| LinearSolverBuilder::new().target(target);
+    assert_eq!(
+        build(target(DrxsTarget {
+            bad_slice: 5,
+            ..DrxsTarget::default()
+        })),
+        Some(BuildError::BadSliceCount(5))
+    );
+    assert_eq!(
+        build(target(DrxsTarget {
+            axes: vec![],
+            ..DrxsTarget::default()
+        })),
+        Some(BuildError::NoAxes)
+    );
+    assert_eq!(
+        build(LinearSolverBuilder::new().min_depth(4).max_depth(4)),
+        None
+    );
+}
+
+#[test]
+fn limit_and_depths() {
+    let scramble: NissSequence<Move333> = "R U F".parse().unwrap();
+    let sols: Vec<_> = LinearSolverBuilder::new()
+        .min_depth(3)
+        .max_depth(3)
+        .build(scramble.clone())
+        .unwrap()
+        .collect();
+    assert!(sols.len() > 2);
+    assert!(
+        sols.iter()
+            .all(|s| s.normal.0.len() == 3 && s.inverse.0.is_empty())
+    );
+
+    let limited: Vec<_> = LinearSolverBuilder::new()
+        .min_depth(3)
+        .max_depth(3)
+        .limit(2)
+        .build(scramble)
+        .unwrap()
+        .collect();
+    assert_eq!(limited, sols[..2]);
+}
+
+#[test]
+fn variants_follow_their_solution() {
+    let scramble: NissSequence<Move333> = "R U F D' (L2 B)".parse().unwrap();
+    let build = |variants| {
+        LinearSolverBuilder::new()
+            .max_depth(4)
+            .niss(true)
+            .variants(variants)
+            .build(scramble.clone())
+            .unwrap()
+            .collect::<Vec<_>>()
+    };
+    let sols = build(false);
+    let with_variants = build(true);
+    assert!(with_variants.len() > sols.len());
+
+    let mut sols = sols.into_iter().peekable();
+    for sol in with_variants {
+        if sols.peek() == Some(&sol) {
+            sols.next();
+            continue;
+        }
+        // A variant is never one of the solutions found anyway
+        assert!(!sols.clone().any(|s| s == sol));
+        let cube = crate::finished_cube(&scramble, &sol);
+        assert!(DrxsTarget::default().is_solved(&cube), "{sol}");
+    }
+    assert!(sols.next().is_none());
+}
+
+#[test]
+fn symmetric_solutions_are_skipped() {
+    use crate::{Symmetry, sort_axes};
+
+    // Every symmetry which keeps the U and D faces on their axis leaves this as it is
+    let scramble: NissSequence<Move333> = "U2 D2".parse().unwrap();
+    let build = |reduce| {
+        LinearSolverBuilder::new()
+            .max_depth(3)
+            .reduce_symmetry(reduce)
+            .build(scramble.clone())
+            .unwrap()
+            .collect::<Vec<_>>()
+    };
+    let sols = build(false);
+    let reduced = build(true);
+    assert!(reduced.len() < sols.len());
+    // Mirrors and swaps of the R and L faces turn R L' into L' R, which is searched as R L'
+    let opposite = |sol: &NissSequence<Move333>| {
+        (sol.normal.0.windows(2)).any(|w| w[0].ty == w[1].ty.opposite())
+    };
+    assert!(reduced.iter().any(opposite));
+
+    let symmetries = Symmetry::fixing(&scramble);
+    assert_eq!(symmetries.len(), 16);
+    let image = |s: &Symmetry, sol| {
+        let mut image = s.apply_niss(sol);
+        sort_axes(&mut image.normal.0);
+        sort_axes(&mut image.inverse.0);
+        image
+    };
+    for (i, sol) in reduced.iter().enumerate() {
+        assert!(sols.contains(sol));
+        // No solution is the image of an earlier one
+        for s in &symmetries {
+            assert!(!reduced[..i].contains(&image(s, sol)), "{sol}");
+        }
+    }
+    for sol in &sols {
+        assert!(symmetries.iter().any(|s| reduced.contains(&image(s, sol))));
+    }
+}
+
+#[test]
+fn prefixes_are_continued() {
+    use crate::finished_cube;
+
+    let scramble: NissSequence<Move333> = "R U F D' (L2 B)".parse().unwrap();
+    let target = DrxsTarget::default();
+    let continue_from = |prefix: &NissSequence<Move333>, niss| {
+        LinearSolverBuilder::new()
+            .max_depth(4)
+            .niss(niss)
+            .prefix(prefix.clone())
+            .build(scramble.clone())
+            .unwrap()
+            .collect::<Vec<_>>()
+    };
+
+    // Carrying on from a prefix is solving the scramble with the prefix on the end
+    let prefix: NissSequence<Move333> = "D F'".parse().unwrap();
+    let mut moved = scramble.clone();
+    moved.normal.0.extend(prefix.normal.0.iter().copied());
+    let expected: Vec<_> = LinearSolverBuilder::new()
+        .max_depth(4)
+        .build(moved)
+        .unwrap()
+        .filter(|sol| !cancels(&prefix.normal, &sol.normal))
+        .map(|sol| format!("{prefix} {sol}"))
+        .collect();
+    let sols = continue_from(&prefix, false);
+    assert!(!sols.is_empty());
+    assert_eq!(
+        sols.iter().map(|s| s.to_string()).collect::<Vec<_>>(),
+        expected
+    );
+
+    for (prefix, niss) in [("D", true), ("D F' (B')", false), ("(B')", true)] {
+        let prefix: NissSequence<Move333> = prefix.parse().unwrap();
+        let sols = continue_from(&prefix, niss);
+        assert!(!sols.is_empty(), "{prefix}");
+        for sol in sols {
+            assert!(sol.normal.0.starts_with(&prefix.normal.0), "{sol}");
+            assert!(sol.inverse.0.starts_with(&prefix.inverse.0), "{sol}");
+            assert!(target.is_solved(&finished_cube(&scramble, &sol)), "{sol}");
+            // Only the moves after the prefix count towards the depth
+            let len = |s: &NissSequence<Move333>| s.normal.0.len() + s.inverse.0.len();
+            assert!(len(&sol) <= len(&prefix) + 4);
+        }
+    }
+}
@@ -0,0 +1,188 @@
+//! Canonical move sequences, which are the sequences that a search has to look at. Turning the
+//! same face twice in a row can be written as one move, and the two faces of an axis commute, so
+//! a sequence is canonical when no face follows itself and the faces of an axis are only ever
+//! turned in the search order, e.g. R L but never L R.
+
+use cube_lib::{
+    cube333::moves::{Move333, Move333Type},
+    moves::MoveSequence,
+};
+
+use crate::{
+    MoveSet,
+    prune::{MOVES, move_index},
+};
+
+/// Whether a move of face `ty` may follow a move of face `prev` in a canonical sequence.
+pub fn may_follow(prev: Move333Type, ty: Move333Type) -> bool {
+    let face = |ty| move_index(Move333 { ty, count: 1 }) / 3;
+    ty != prev && !(ty == prev.opposite() && face(ty) < face(prev))
+}
+
+/// Puts each pair of moves on opposite faces of an axis in the search order, which makes a sequence
+/// canonical if no face follows itself. Mapping a canonical sequence by a symmetry can swap the
+/// order of the faces of an axis, e.g. R L' U' becomes L' R U' in a mirror.
+pub(crate) fn sort_axes(moves: &mut [Move333]) {
+    for i in 1..moves.len() {
+        if moves[i].ty == moves[i - 1].ty.opposite() && !may_follow(moves[i - 1].ty, moves[i].ty) {
+            moves.swap(i - 1, i);
+        }
+    }
+}
+
+/// The first move of `set` in the search order that may follow a move of type `prev`, if there is
+/// one.
+pub(crate) fn first_move(prev: Option<Move333Type>, set: MoveSet) -> Option<Move333> {
+    moves_from(0, prev, set)
+}
+
+/// The move of `set` after `m` in the search order, given that the move before `m` had type
+/// `prev`. If there are no moves of `set` left that may follow `prev`, None is returned.
+pub(crate) fn following_move(
+    prev: Option<Move333Type>,
+    m: Move333,
+    set: MoveSet,
+) -> Option<Move333> {
+    moves_from(move_index(m) + 1, prev, set)
+}
+
+/// The first move of `set` that may follow `prev`, starting at index `i` of `MOVES`.
+fn moves_from(i: usize, prev: Option<Move333Type>, set: MoveSet) -> Option<Move333> {
+    MOVES[i..]
+        .iter()
+        .copied()
+        .find(|&m| set.contains(m) && prev.is_none_or(|prev| may_follow(prev, m.ty)))
+}
+
+/// The position of a move sequence in the search order of HTM. Sequences of the same length
+/// compare (lexicographically) in the order they are searched.
+pub(crate) fn search_key(moves: &[Move333]) -> impl Iterator<Item = usize> + '_ {
+    moves.iter().map(|&m| move_index(m))
+}
+
+/// The last of the variations of R R' L L' moves that follow the solution `moves`, which is five
+/// sequences after it in the search order of HTM. If the search space ends before that, None is
+/// returned.
+pub(crate) fn last_variation(moves: &[Move333]) -> Option<Vec<Move333>> {
+    let mut last = moves.to_vec();
+    (0..5)
+        .all(|_| next_sequence(&mut last, MoveSet::HTM))
+        .then_some(last)
+}
+
+/// The first sequence of `len` moves of `set` in the search order, if there is one.
+pub(crate) fn first_sequence(len: usize, set: MoveSet) -> Option<Vec<Move333>> {
+    let mut moves = Vec::with_capacity(len);
+    fill_sequence(&mut moves, len, set).then_some(moves)
+}
+
+/// Extends `moves` to `len` moves with the first moves of `set` in the search order, moving on to
+/// later sequences if `moves` can't be extended. If there are no sequences left, false is
+/// returned.
+fn fill_sequence(moves: &mut Vec<Move333>, len: usize, set: MoveSet) -> bool {
+    while moves.len() < len {
+        match first_move(moves.last().map(|m| m.ty), set) {
+            Some(m) => moves.push(m),
+            None => {
+                if !advance_sequence(moves, set) {
+                    return false;
+                }
+            }
+        }
+    }
+    true
+}
+
+/// Replaces the last move of `moves` with the next move of `set` in the search order, dropping
+/// moves off the end when they have been exhausted. If there are no moves left, false is returned.
+fn advance_sequence(moves: &mut Vec<Move333>, set: MoveSet) -> bool {
+    loop {
+        let Some(m) = moves.pop() else {
+            return false;
+        };
+        if let Some(m) = following_move(moves.last().map(|m| m.ty), m, set) {
+            moves.push(m);
+            return true;
+        }
+    }
+}
+
+/// Steps `moves` on to the next sequence of moves of `set` with the same length in the search
+/// order. If there are no sequences left, false is returned.
+pub(crate) fn next_sequence(moves: &mut Vec<Move333>, set: MoveSet) -> bool {
+    let len = moves.len();
+    advance_sequence(moves, set) && fill_sequence(moves, len, set)
+}
+
+/// Every canonical sequence of a given length made of the moves of a move set, in the search
+/// order.
+pub struct CanonicalSequences {
+    set: MoveSet,
+    /// The sequence to return next, if there are any left.
+    next: Option<Vec<Move333>>,
+}
+
+impl CanonicalSequences {
+    pub fn new(len: usize, set: MoveSet) -> CanonicalSequences {
+        CanonicalSequences {
+            set,
+            next: first_sequence(len, set),
+        }
+    }
+}
+
+impl Iterator for CanonicalSequences {
+    type Item = MoveSequence<Move333>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let moves = self.next.take()?;
+        let mut following = moves.clone();
+        if next_sequence(&mut following, self.set) {
+            self.next = Some(following);
+        }
+        Some(MoveSequence(moves))
+    }
+}
+
+#[test]
+fn canonical_counts() {
+    // The number of canonical sequences of each length in HTM. From four moves on there are more
+    // sequences than positions, since some sequences reach the same position.
+    let counts: Vec<_> = (0..=5)
+        .map(|len| CanonicalSequences::new(len, MoveSet::HTM).count())
+        .collect();
+    assert_eq!(counts, [1, 18, 243, 3240, 43254, 577368]);
+
+    for moves in CanonicalSequences::new(3, MoveSet::HTM) {
+        assert!(moves.0.windows(2).all(|w| may_follow(w[0].ty, w[1].ty)));
+    }
+    assert!(may_follow(Move333Type::R, Move333Type::L));
+    assert!(!may_follow(Move333Type::L, Move333Type::R));
+    assert!(!may_follow(Move333Type::U, Move333Type::U));
+}
+
+#[test]
+fn sequences_use_the_move_set() {
+    let count = |len, set: &str| {
+        let set: MoveSet = set.parse().unwrap();
+        CanonicalSequences::new(len, set)
+            .inspect(|moves| assert!(moves.0.iter().all(|&m| set.contains(m))))
+            .count()
+    };
+    // Only R and U moves alternate, and a single face can't make two moves in a row
+    assert_eq!(count(3, "R U"), 2 * 3 * 3 * 3);
+    assert_eq!(count(2, "R"), 0);
+    assert_eq!(count(0, "R"), 1);
+    // A half turn of either face of an axis can follow the other, but only in one order
+    assert_eq!(count(2, "R2 L2"), 1);
+    // Ten moves, less the 22 pairs on the same face and the 11 pairs of opposite faces out of
+    // order
+    assert_eq!(count(2, "U D F2 B2 R2 L2"), 10 * 10 - 22 - 11);
+}
+
+#[test]
+fn sorted_axes_are_canonical() {
+    let mut moves: MoveSequence<Move333> = "L' R U' D B F2".parse().unwrap();
+    sort_axes(&mut moves.0);
+    assert_eq!(moves, "R L' U' D F2 B".parse().unwrap());
+}
@@ -0,0 +1,344 @@
+//! Carrying on from a dr-xs: the slice edges left out of the slice have to be put back in, either
+//! to reach a full domino reduction or to go straight on to a half turn reduction.
+
+use std::{collections::VecDeque, fmt, str::FromStr};
+
+use cube_lib::{
+    cube333::{CubieCube, axis::Axis, moves::Move333},
+    moves::MoveSequence,
+};
+
+use crate::{
+    BuildError, CornerPattern, DrxsTarget, MoveSet, SliceFlip, drxs_cases,
+    pipeline::{cancels, is_dr, is_htr, make_moves, search},
+    prune::{PruningTable, eo_coord, eo_distances},
+    search_key,
+};
+
+/// What a `SliceFinisher` finishes a dr-xs with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FinishGoal {
+    /// A domino reduction on the axis of the dr-xs.
+    Dr,
+    /// A half turn reduction.
+    Htr,
+}
+
+impl fmt::Display for FinishGoal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FinishGoal::Dr => "dr",
+            FinishGoal::Htr => "htr",
+        })
+    }
+}
+
+impl FromStr for FinishGoal {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "dr" => Ok(FinishGoal::Dr),
+            "htr" => Ok(FinishGoal::Htr),
+            _ => Err(format!("unknown goal `{s}`, expected one of dr or htr")),
+        }
+    }
+}
+
+/// Configures a `SliceFinisher`.
+#[derive(Debug, Clone)]
+pub struct FinishBuilder {
+    max_depth: usize,
+    goal: FinishGoal,
+    any_moves: bool,
+}
+
+impl Default for FinishBuilder {
+    fn default() -> Self {
+        FinishBuilder {
+            max_depth: 10,
+            goal: FinishGoal::Dr,
+            any_moves: false,
+        }
+    }
+}
+
+impl FinishBuilder {
+    pub fn new() -> FinishBuilder {
+        FinishBuilder::default()
+    }
+
+    /// Don't return finishes longer than `d` moves.
+    pub fn max_depth(mut self, d: usize) -> Self {
+        self.max_depth = d;
+        self
+    }
+
+    pub fn goal(mut self, goal: FinishGoal) -> Self {
+        self.goal = goal;
+        self
+    }
+
+    /// Let finishes use any move, so that they may leave the domino reduction on the way as long
+    /// as they end in one. Otherwise only the moves keeping a domino reduction on the axis of the
+    /// dr-xs are used, see `SliceFinisher`.
+    pub fn any_moves(mut self, any_moves: bool) -> Self {
+        self.any_moves = any_moves;
+        self
+    }
+
+    /// Start finishing the dr-xs that `cube` is in. A domino reduction may be on any axis the
+    /// cube is in a dr-xs on.
+    pub fn build(self, cube: CubieCube) -> Result<SliceFinisher, BuildError> {
+        let axes: Vec<_> = Axis::AXES
+            .into_iter()
+            .filter(|&a| !drxs_cases(&cube, a).is_empty())
+            .collect();
+        if axes.is_empty() {
+            return Err(BuildError::NotDrxs);
+        }
+        let target = DrxsTarget {
+            axes: match self.goal {
+                FinishGoal::Dr => axes.clone(),
+                // A half turn reduction is a domino reduction on every axis
+                FinishGoal::Htr => Axis::AXES.to_vec(),
+            },
+            bad_slice: 0,
+            flip: SliceFlip::Either,
+            corners: vec![CornerPattern::Oriented],
+        };
+        Ok(SliceFinisher {
+            table: PruningTable::get(&target),
+            cube,
+            axes,
+            config: self,
+            previous: MoveSequence(vec![]),
+            length: 0,
+            found: VecDeque::new(),
+        })
+    }
+
+    /// Start finishing the dr-xs that `sol` reaches from `cube`, such as a solution of a
+    /// `LinearSolver`. Finishes which start on the face `sol` ends with are skipped, since they
+    /// could be written with fewer moves.
+    pub fn after(
+        self,
+        cube: &CubieCube,
+        sol: &MoveSequence<Move333>,
+    ) -> Result<SliceFinisher, BuildError> {
+        let mut finisher = self.build(make_moves(cube.clone(), sol))?;
+        finisher.previous = sol.clone();
+        Ok(finisher)
+    }
+}
+
+/// Finds the ways to finish a dr-xs, as configured by a `FinishBuilder`, with an iterator
+/// interface. Finishes are returned in order of length, so the first is optimal.
+///
+/// The moves keep the domino reduction on the axis of the dr-xs intact, so they are
+/// ⟨U, D, R2, L2, F2, B2⟩ for a dr-xs on UD. These never move an edge in or out of the slice, so
+/// only a dr-xs without bad slice edges which is already a domino reduction can be finished, and
+/// the only way to finish it with a domino reduction is to do nothing. `FinishBuilder::any_moves`
+/// searches every move instead.
+pub struct SliceFinisher {
+    cube: CubieCube,
+    /// The axes that the cube is in a dr-xs on.
+    axes: Vec<Axis>,
+    config: FinishBuilder,
+    /// Bounds the distance to a full dr-xs, which the goal needs on top of the edge orientation.
+    table: &'static PruningTable,
+    /// The moves that reached the dr-xs, if they are known.
+    previous: MoveSequence<Move333>,
+    /// The length of the finishes being searched for.
+    length: usize,
+    /// Finishes of the current length which haven't been returned yet.
+    found: VecDeque<MoveSequence<Move333>>,
+}
+
+impl SliceFinisher {
+    /// A lower bound on the moves needed to reach a domino reduction on axis `a`.
+    fn dr_bound(&self, c: &CubieCube, a: Axis) -> usize {
+        let eo = Axis::AXES
+            .into_iter()
+            .filter(|&b| b != a)
+            .map(|b| eo_distances(b)[eo_coord(c, b)] as usize);
+        eo.chain([self.table.axis_lower_bound(self.table.coords(c), a)])
+            .max()
+            .unwrap()
+    }
+
+    /// Finds every finish with `self.length` moves.
+    fn find(&mut self) {
+        let found = if self.config.any_moves {
+            self.find_any()
+        } else {
+            self.find_keeping_dr()
+        };
+        self.found.extend(
+            found
+                .into_iter()
+                .filter(|finish| !cancels(&self.previous, finish)),
+        );
+    }
+
+    /// A lower bound on the moves needed to reach a half turn reduction.
+    fn htr_bound(&self, c: &CubieCube) -> usize {
+        Axis::AXES
+            .into_iter()
+            .map(|a| self.dr_bound(c, a))
+            .max()
+            .unwrap()
+    }
+
+    /// The finishes using the moves which keep a domino reduction on one of the axes of the
+    /// dr-xs, for the axes that the cube is in a domino reduction on.
+    fn find_keeping_dr(&self) -> Vec<MoveSequence<Move333>> {
+        let mut found = vec![];
+        for &a in self.axes.iter().filter(|&&a| is_dr(&self.cube, a)) {
+            match self.config.goal {
+                FinishGoal::Dr if self.length == 0 => found.push(MoveSequence(vec![])),
+                FinishGoal::Dr => {}
+                FinishGoal::Htr => found.extend(search(
+                    &self.cube,
+                    self.length,
+                    MoveSet::dr(a),
+                    |c| self.htr_bound(c),
+                    is_htr,
+                )),
+            }
+        }
+        // A finish can keep the domino reduction on more than one axis
+        found.sort_by(|a, b| search_key(&a.0).cmp(search_key(&b.0)));
+        found.dedup();
+        found
+    }
+
+    /// The finishes using any move.
+    fn find_any(&self) -> Vec<MoveSequence<Move333>> {
+        match self.config.goal {
+            FinishGoal::Dr => search(
+                &self.cube,
+                self.length,
+                MoveSet::HTM,
+                |c| {
+                    self.axes
+                        .iter()
+                        .map(|&a| self.dr_bound(c, a))
+                        .min()
+                        .unwrap()
+                },
+                |c| self.axes.iter().any(|&a| is_dr(c, a)),
+            ),
+            FinishGoal::Htr => search(
+                &self.cube,
+                self.length,
+                MoveSet::HTM,
+                |c| self.htr_bound(c),
+                is_htr,
+            ),
+        }
+    }
+}
+
+impl Iterator for SliceFinisher {
+    type Item = MoveSequence<Move333>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(finish) = self.found.pop_front() {
+                return Some(finish);
+            }
+            if self.length > self.config.max_depth {
+                return None;
+            }
+            self.find();
+            self.length += 1;
+        }
+    }
+}
+
+#[test]
+fn finishes_keep_dr() {
+    use crate::LinearSolver;
+
+    let cube = CubieCube::SOLVED.make_niss_moves("R U F".parse().unwrap());
+    assert_eq!(
+        FinishBuilder::new().build(cube.clone()).err(),
+        Some(BuildError::NotDrxs)
+    );
+
+    // A domino reduction is finished by doing nothing, and goes on to htr without leaving it
+    let mut solver = LinearSolver::new(cube.clone());
+    solver.set_target(DrxsTarget {
+        corners: vec![CornerPattern::Oriented],
+        ..DrxsTarget::default()
+    });
+    let dr = solver.next().unwrap();
+    let start = make_moves(cube.clone(), &dr);
+    let axis = Axis::AXES.into_iter().find(|&a| is_dr(&start, a)).unwrap();
+    let finishes: Vec<_> = FinishBuilder::new()
+        .max_depth(5)
+        .after(&cube, &dr)
+        .unwrap()
+        .collect();
+    assert_eq!(finishes, [MoveSequence(vec![])]);
+    let htrs: Vec<_> = FinishBuilder::new()
+        .max_depth(6)
+        .goal(FinishGoal::Htr)
+        .after(&cube, &dr)
+        .unwrap()
+        .take(20)
+        .collect();
+    assert!(!htrs.is_empty(), "{dr}");
+    assert!(htrs.is_sorted_by_key(|f| f.0.len()));
+    for htr in &htrs {
+        assert!(
+            htr.0.iter().all(|&m| MoveSet::dr(axis).contains(m)),
+            "{htr}"
+        );
+        assert!(is_htr(&make_moves(start.clone(), htr)), "{htr}");
+    }
+
+    let mut solver = LinearSolver::new(cube.clone());
+    solver.set_target(DrxsTarget {
+        bad_slice: 2,
+        corners: vec![CornerPattern::Oriented],
+        ..DrxsTarget::default()
+    });
+    for drxs in solver.take(2) {
+        // The slice edges can't be put back without leaving the domino reduction
+        let finish = |goal, any_moves| {
+            FinishBuilder::new()
+                .max_depth(5)
+                .goal(goal)
+                .any_moves(any_moves)
+                .after(&cube, &drxs)
+                .unwrap()
+        };
+        assert_eq!(finish(FinishGoal::Dr, false).next(), None);
+
+        let start = make_moves(cube.clone(), &drxs);
+        let finishes: Vec<_> = finish(FinishGoal::Dr, true).collect();
+        assert!(!finishes.is_empty(), "{drxs}");
+        assert!(finishes.is_sorted_by_key(|f| f.0.len()));
+        for finish in &finishes {
+            assert!(!cancels(&drxs, finish));
+            let finished = make_moves(start.clone(), finish);
+            assert!(
+                Axis::AXES.into_iter().any(|a| is_dr(&finished, a)),
+                "{finish}"
+            );
+        }
+
+        let htr = FinishBuilder::new()
+            .max_depth(6)
+            .goal(FinishGoal::Htr)
+            .any_moves(true)
+            .after(&cube, &drxs)
+            .unwrap()
+            .next()
+            .unwrap();
+        assert!(htr.0.len() >= finishes[0].0.len());
+        assert!(is_htr(&make_moves(start, &htr)), "{htr}");
+    }
+}
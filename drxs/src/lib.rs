@@ -1,58 +1,67 @@
 use cube_lib::{
-    cube333::{
-        CubieCube,
-        axis::Axis,
-        corner::CornerTwist,
-        edge::EdgeFlip,
-        moves::{Move333, Move333Type},
-    },
+    cube333::{CubieCube, axis::Axis, corner::CornerTwist, moves::Move333},
     moves::{Move, MoveSequence},
-    mv,
 };
 
-pub fn next_ty(t: Move333Type) -> Option<Move333Type> {
-    match t {
-        Move333Type::R => Some(Move333Type::L),
-        Move333Type::L => Some(Move333Type::U),
-        Move333Type::U => Some(Move333Type::D),
-        Move333Type::D => Some(Move333Type::F),
-        Move333Type::F => Some(Move333Type::B),
-        Move333Type::B => None,
-    }
-}
+mod analysis;
+mod budget;
+mod builder;
+mod canonical;
+mod finish;
+mod move_set;
+mod niss;
+mod parallel;
+mod pipeline;
+mod premove;
+mod prune;
+mod score;
+#[cfg(feature = "server")]
+pub mod server;
+mod subset;
+mod symmetry;
+mod target;
+
+pub use analysis::{Histogram, optimal_length, random_state};
+pub use budget::{Budget, CancelToken};
+pub use builder::{BuildError, LinearSolverBuilder, Solutions};
+pub use canonical::{CanonicalSequences, may_follow};
+pub use finish::{FinishBuilder, FinishGoal, SliceFinisher};
+pub use move_set::MoveSet;
+pub use niss::{NissSolver, finished_cube};
+pub use pipeline::{Pipeline, PipelineBuilder, Skeleton};
+pub use premove::{PremoveBuilder, PremoveSolution, PremoveSolver};
+pub use prune::cache_tables;
+pub use score::Scorer;
+pub use subset::{HtrSubset, axis_subset, drxs_subset, htr_subset, solution_subset};
+pub use symmetry::Symmetry;
+pub use target::{
+    AxisStatus, CornerPattern, DrxsInfo, DrxsTarget, SliceFlip, axis_name, drxs_cases, parse_axis,
+};
+
+use canonical::{
+    first_move, first_sequence, following_move, last_variation, next_sequence, search_key,
+    sort_axes,
+};
+use prune::{AxisCoord, PruningTable};
+
+use CornerTwist as CT;
+/// The corner orientations (relative to the axis of the slice edges) that a dr-xs can have.
+#[rustfmt::skip]
+const COS: [[CornerTwist; 8]; 3] = [[CT::Oriented; 8],
+[CT::Clockwise, CT::AntiClockwise, CT::Clockwise, CT::AntiClockwise, CT::Clockwise, CT::AntiClockwise, CT::Clockwise, CT::AntiClockwise],
+[CT::AntiClockwise, CT::Clockwise, CT::AntiClockwise, CT::Clockwise, CT::AntiClockwise, CT::Clockwise, CT::AntiClockwise, CT::Clockwise]];
+/// The edge positions making up each of the three slices. The slice edges of the UD axis have to
+/// be in `IDXS[j]` when the corners have the orientation `COS[j]`, see `symmetry::axis_slice` for
+/// the other axes.
+const IDXS: [[usize; 4]; 3] = [[8, 9, 10, 11], [0, 2, 4, 6], [1, 3, 5, 7]];
 
-/// Determines whether a CubieCube is in dr-xs for some axis.
-fn is_solved(c: &CubieCube) -> bool {
-    use CornerTwist as CT;
-    #[rustfmt::skip]
-    const COS: [[CornerTwist; 8]; 3] = [[CT::Oriented; 8],
-    [CT::Clockwise, CT::AntiClockwise, CT::Clockwise, CT::AntiClockwise, CT::Clockwise, CT::AntiClockwise, CT::Clockwise, CT::AntiClockwise],
-    [CT::AntiClockwise, CT::Clockwise, CT::AntiClockwise, CT::Clockwise, CT::AntiClockwise, CT::Clockwise, CT::AntiClockwise, CT::Clockwise]];
-    const IDXS: [[usize; 4]; 3] = [[8, 9, 10, 11], [0, 2, 4, 6], [1, 3, 5, 7]];
-
-    fn idx_i(a: Axis, j: usize) -> usize {
-        match a {
-            Axis::FB => (j + 2) % 3,
-            Axis::LR => (j + 1) % 3,
-            Axis::UD => j,
-        }
-    }
-
-    Axis::AXES.iter().any(|&a| {
-        let co = c.axis_co(a);
-        let eo = c.axis_eo(a);
-        (0..3).any(|j| {
-            let idxs = IDXS[idx_i(a, j)];
-            COS[j] == co
-                && idxs.into_iter().all(|k| match a {
-                    Axis::FB => c.ep[k].s_slice(),
-                    Axis::LR => c.ep[k].m_slice(),
-                    Axis::UD => c.ep[k].e_slice(),
-                })
-                && (idxs.into_iter().all(|k| eo[k] == EdgeFlip::Oriented)
-                    || idxs.into_iter().all(|k| eo[k] == EdgeFlip::Flipped))
-        })
-    })
+/// Whether the edge at position `k` belongs to the slice of axis `a`.
+fn is_slice_edge(c: &CubieCube, a: Axis, k: usize) -> bool {
+    match a {
+        Axis::FB => c.ep[k].s_slice(),
+        Axis::LR => c.ep[k].m_slice(),
+        Axis::UD => c.ep[k].e_slice(),
+    }
 }
 
 /// Finds linear dr-xs solutions (on the normal side of a scramble) with an iterator interface.
@@ -60,112 +69,287 @@ pub struct LinearSolver {
     /// The current search depth that the solver is at. This will increase over time.
     depth: usize,
     /// The maximim permitted search depth. The solver will terminate past this depth. The user can
-    /// control this maximum with `set_max_depth`.
+    /// control this maximum with `LinearSolverBuilder::max_depth`.
     max_depth: usize,
+    /// Whether solutions that only differ from the previous solution by the direction of the final
+    /// quarter turns should also be returned.
+    all: bool,
+    /// Whether to skip parts of the search space using the pruning table. This is only ever turned
+    /// off to test the pruning against a blind search.
+    prune: bool,
+    target: DrxsTarget,
+    /// The moves that solutions may use.
+    move_set: MoveSet,
+    table: &'static PruningTable,
     cube: CubieCube,
     moves: Vec<Move333>,
+    /// The number of moves at the start of `moves` which are never changed, see `set_prefix`.
+    fixed: usize,
+    /// The pruning table coordinates of the cube after each prefix of `moves`.
+    coords: Vec<[AxisCoord; 3]>,
+    budget: Budget,
+    /// The number of nodes visited so far.
+    nodes: u64,
+    /// Whether the budget has run out, which ends the search.
+    stopped: bool,
+    /// The deepest depth which has been searched all the way through.
+    finished: Option<usize>,
 }
 
 impl LinearSolver {
     pub fn new(cube: CubieCube) -> LinearSolver {
+        let target = DrxsTarget::default();
+        let table = PruningTable::get(&target);
         LinearSolver {
             depth: 0,
             max_depth: 20,
+            all: false,
+            prune: true,
+            target,
+            move_set: MoveSet::HTM,
+            table,
+            coords: vec![table.coords(&cube)],
             cube,
             moves: vec![],
+            fixed: 0,
+            budget: Budget::new(),
+            nodes: 0,
+            stopped: false,
+            finished: None,
         }
     }
 
-    // TODO this stuff should really be in a builder, not on the (potentially currently iterating)
-    // solver.
+    // These are only used before iterating, either by `LinearSolverBuilder` or by `NissSolver`
+    // for its inner solvers.
 
-    pub fn set_min_depth(&mut self, d: usize) {
-        // SUPER SCARY THAT THIS IS PUBLIC oh well i'm the only one actually using this code lolol
-        self.depth = d;
+    pub(crate) fn set_min_depth(&mut self, d: usize) {
+        assert!(self.moves.len() == self.fixed && d >= self.fixed);
+        // The search space for depth 0 is just the empty sequence, every other depth has to be
+        // filled with moves before we start searching it.
+        if d > 0 {
+            self.depth = d - 1;
+            self.increase_depth();
+        }
     }
 
-    pub fn set_max_depth(&mut self, d: usize) {
+    pub(crate) fn set_max_depth(&mut self, d: usize) {
         self.max_depth = d;
     }
 
+    /// Also return the solutions which only differ by the direction of their final quarter turns.
+    pub(crate) fn set_all(&mut self, all: bool) {
+        self.all = all;
+    }
+
+    /// Only look for the dr-xs states described by `target`. This restarts the search at the
+    /// current depth.
+    pub(crate) fn set_target(&mut self, target: DrxsTarget) {
+        // The coordinates of the prefix would have to be worked out again
+        assert!(self.fixed == 0);
+        while self.pop_move().is_some() {}
+        self.table = PruningTable::get(&target);
+        self.target = target;
+        self.coords = vec![self.table.coords(&self.cube)];
+        self.restart_depth();
+    }
+
+    /// Only use the moves in `set`. This restarts the search at the current depth.
+    pub(crate) fn set_move_set(&mut self, set: MoveSet) {
+        self.reset_moves();
+        self.move_set = set;
+        self.restart_depth();
+    }
+
+    /// Only search the sequences which start with `prefix`, which has to be canonical in the
+    /// search order. This restarts the search, at the length of the prefix if the current depth
+    /// is shorter.
+    pub(crate) fn set_prefix(&mut self, prefix: &[Move333]) {
+        self.fixed = 0;
+        self.reset_moves();
+        for &m in prefix {
+            self.add_move(m);
+        }
+        self.fixed = prefix.len();
+        self.depth = self.depth.max(self.fixed);
+        self.restart_depth();
+    }
+
+    /// Search from `cube` instead, starting again from depth 0. The table and the other settings
+    /// are kept, so this is much cheaper than making a new solver.
+    pub(crate) fn set_cube(&mut self, cube: CubieCube) {
+        assert!(self.fixed == 0);
+        self.moves.clear();
+        self.coords = vec![self.table.coords(&cube)];
+        self.cube = cube;
+        self.depth = 0;
+        self.finished = None;
+    }
+
+    /// The pruning table's lower bound on the length of a solution from the cube.
+    pub(crate) fn lower_bound(&self) -> usize {
+        self.table.lower_bound(self.coords[0])
+    }
+
+    /// Give up the search once `budget` runs out, see `out_of_budget`.
+    pub(crate) fn set_budget(&mut self, budget: Budget) {
+        self.budget = budget;
+    }
+
+    /// Whether the search ended because its budget ran out, rather than by reaching the maximum
+    /// depth. Some solutions may not have been found.
+    pub fn out_of_budget(&self) -> bool {
+        self.stopped
+    }
+
+    /// The deepest depth whose search has been finished, if any. Every solution of this length or
+    /// shorter (down to the minimum depth) has been returned, so the shortest of them are optimal.
+    pub fn finished_depth(&self) -> Option<usize> {
+        self.finished
+    }
+
+    /// The number of nodes the search has visited.
+    pub fn nodes(&self) -> u64 {
+        self.nodes
+    }
+
+    /// Removes every move after the fixed prefix.
+    fn reset_moves(&mut self) {
+        while self.moves.len() > self.fixed {
+            self.pop_move();
+        }
+    }
+
+    /// Fill the moves again after they have been cleared, without changing the depth.
+    fn restart_depth(&mut self) {
+        if self.depth > 0 {
+            self.depth -= 1;
+            self.increase_depth();
+        }
+    }
+
     fn add_move(&mut self, mv: Move333) {
+        if !self.budget.spend(self.nodes) {
+            self.stopped = true;
+        }
+        self.nodes += 1;
         self.moves.push(mv);
         self.cube = self.cube.clone().make_move(mv);
+        let coords = self.table.make_move(self.coords(), mv);
+        self.coords.push(coords);
     }
 
     fn pop_move(&mut self) -> Option<Move333> {
         let m = self.moves.pop()?;
         self.cube = self.cube.clone().make_move(m.inverse());
+        self.coords.pop();
         Some(m)
     }
 
-    fn choose_new_move(&mut self) {
-        assert!(self.depth > self.moves.len());
-        use Move333Type as T;
+    fn coords(&self) -> [AxisCoord; 3] {
+        *self.coords.last().unwrap()
+    }
+
+    /// Whether the pruning table tells us that no solution starts with the current moves.
+    fn pruned(&self) -> bool {
+        self.prune && self.table.lower_bound(self.coords()) > self.depth - self.moves.len()
+    }
 
-        match self.moves.last().copied() {
-            // Don't dupe R moves
-            Some(m) if m.ty == T::R => self.add_move(mv!(L, 1)),
-            // We never check L R because we'll do R L instead
-            // hence we skip straight to L U
-            Some(m) if m.ty == T::L => self.add_move(mv!(U, 1)),
-            _ => self.add_move(mv!(R, 1)),
+    /// Adds the first move that may follow the current moves. If there isn't one, false is
+    /// returned.
+    fn choose_new_move(&mut self) -> bool {
+        assert!(self.depth > self.moves.len());
+        if self.stopped {
+            return false;
+        }
+        match first_move(self.moves.last().map(|m| m.ty), self.move_set) {
+            Some(m) => {
+                self.add_move(m);
+                true
+            }
+            None => false,
         }
     }
 
-    /// Increase the current search depth and reset the search space.
+    /// Increase the current search depth and reset the search space. If the pruning table rules
+    /// out the whole search space at this depth, only the fixed prefix is left in the moves.
     fn increase_depth(&mut self) {
-        assert!(self.moves.is_empty());
+        assert!(self.moves.len() == self.fixed);
 
         self.depth += 1;
 
-        for _ in 0..self.depth {
-            self.choose_new_move();
+        self.fill_pruned();
+    }
+
+    /// Fills the moves up to the current depth, skipping over prefixes that the pruning table
+    /// rules out. If the search space is exhausted, false is returned.
+    fn fill_pruned(&mut self) -> bool {
+        loop {
+            if self.pruned() {
+                if !self.next_move() {
+                    return false;
+                }
+            } else if self.moves.len() == self.depth {
+                return true;
+            } else if !self.choose_new_move() && !self.next_move() {
+                return false;
+            }
         }
     }
 
     /// Attempts to get the next move in the search space. If we have exhausted the search space,
-    /// false is returned.
+    /// or the budget has run out, false is returned.
     fn next_state(&mut self) -> bool {
+        if !self.next_move() {
+            return false;
+        }
+        while self.moves.len() < self.depth {
+            if !self.choose_new_move() && !self.next_move() {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Like `next_state`, but skips over the parts of the search space that the pruning table
+    /// rules out.
+    fn next_pruned_state(&mut self) -> bool {
+        self.next_move() && self.fill_pruned()
+    }
+
+    /// Skips over the variations of R R' L L' moves that follow a solution, which are the next five
+    /// sequences in the search order of HTM (whether or not they are in the move set). This leaves
+    /// the solver on a sequence that hasn't been checked yet. If the search space is exhausted,
+    /// false is returned.
+    fn skip_variations(&mut self) -> bool {
+        let Some(last) = last_variation(&self.moves) else {
+            self.reset_moves();
+            return false;
+        };
+        while search_key(&self.moves).le(search_key(&last)) {
+            if !self.next_state() {
+                return false;
+            }
+        }
+        self.fill_pruned()
+    }
+
+    /// Replaces the last move with the next move in the search order, dropping moves off the end
+    /// when they have been exhausted. If the whole search space is exhausted, or the budget has run
+    /// out, false is returned.
+    fn next_move(&mut self) -> bool {
+        if self.stopped || self.moves.len() == self.fixed {
+            return false;
+        }
         let Some(last_end) = self.pop_move() else {
             return false;
         };
 
-        if last_end.count < 3 {
-            assert!(last_end.count >= 1);
-            let m = Move333 {
-                count: last_end.count + 1,
-                ..last_end
-            };
-            self.add_move(m);
-            true
-        } else {
-            assert!(last_end.count == 3);
-
-            // i love match match
-            match match (
-                next_ty(last_end.ty),
-                self.moves.last().cloned().map(|m| m.ty),
-            ) {
-                (Some(ty), Some(ty2)) if ty == ty2.opposite() => next_ty(ty).and_then(next_ty),
-                (Some(ty), Some(ty2)) if ty == ty2 => next_ty(ty),
-                (Some(ty), _) => Some(ty),
-                (None, _) => None,
-            } {
-                Some(ty) => {
-                    let m = Move333 { ty, count: 1 };
-                    self.add_move(m);
-                    true
-                }
-                None => {
-                    if self.next_state() {
-                        self.choose_new_move();
-                        true
-                    } else {
-                        false
-                    }
-                }
+        match following_move(self.moves.last().map(|m| m.ty), last_end, self.move_set) {
+            Some(m) => {
+                self.add_move(m);
+                true
             }
+            None => self.next_move(),
         }
     }
 }
@@ -174,24 +358,179 @@ impl Iterator for LinearSolver {
     type Item = MoveSequence<Move333>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        while self.depth <= self.max_depth {
+        while self.depth <= self.max_depth && !self.stopped {
             // cursed do while loop
             while {
-                if is_solved(&self.cube) {
+                if self.moves.len() == self.depth && self.target.is_solved(&self.cube) {
                     let mvs = self.moves.clone();
-                    // skip over the variations of R R' L L' moves
-                    for _ in 0..5 {
-                        self.next_state();
-                    }
-                    if !self.next_state() {
+                    let more = if self.all {
+                        self.next_pruned_state()
+                    } else {
+                        self.skip_variations()
+                    };
+                    if !more && !self.stopped {
+                        self.finished = Some(self.depth);
                         self.increase_depth();
                     }
                     return Some(MoveSequence(mvs));
                 }
-                self.next_state()
+                self.next_pruned_state()
             } {}
-            self.increase_depth();
+            if !self.stopped {
+                self.finished = Some(self.depth);
+                self.increase_depth();
+            }
         }
         None
     }
 }
+
+/// The variations of a solution made by reversing the direction of its final quarter turns (the
+/// last move, and the move before it if they are on the same axis) which still reach `target`
+/// from `cube`. These are skipped by the solvers unless every solution is asked for. The solution
+/// itself isn't included, and the variations are in the search order.
+pub fn quarter_turn_variants(
+    cube: &CubieCube,
+    sol: &MoveSequence<Move333>,
+    target: &DrxsTarget,
+) -> Vec<MoveSequence<Move333>> {
+    let moves = &sol.0;
+    let Some(&last) = moves.last() else {
+        return vec![];
+    };
+    let n = moves.len();
+    let before = (n >= 2 && moves[n - 2].ty == last.ty.opposite()).then(|| moves[n - 2]);
+
+    // The two final moves, each either as they are or reversed if they are quarter turns
+    let reversals = |m: Move333| {
+        let mut ms = vec![m];
+        if m.count != 2 {
+            ms.push(m.inverse());
+        }
+        ms
+    };
+    let mut variants = vec![];
+    for b in before.map_or(vec![None], |b| reversals(b).into_iter().map(Some).collect()) {
+        for l in reversals(last) {
+            let mut variant = moves[..n - before.map_or(1, |_| 2)].to_vec();
+            variant.extend(b);
+            variant.push(l);
+            if variant != *moves
+                && target.is_solved(&variant.iter().fold(cube.clone(), |c, &m| c.make_move(m)))
+            {
+                variants.push(MoveSequence(variant));
+            }
+        }
+    }
+    variants.sort_by(|a, b| search_key(&a.0).cmp(search_key(&b.0)));
+    variants
+}
+
+#[test]
+fn pruning_matches_blind_search() {
+    for scramble in [
+        "R U F",
+        "F2 L' D B R'",
+        "U' R2 F B' D L2",
+        "L F' U2 R D' B (R2 U)",
+    ] {
+        let cube = CubieCube::SOLVED.make_niss_moves(scramble.parse().unwrap());
+        for all in [false, true] {
+            let mut pruned = LinearSolver::new(cube.clone());
+            pruned.set_max_depth(5);
+            pruned.set_all(all);
+            let mut blind = LinearSolver::new(cube.clone());
+            blind.prune = false;
+            blind.set_max_depth(5);
+            blind.set_all(all);
+            assert_eq!(pruned.collect::<Vec<_>>(), blind.collect::<Vec<_>>());
+        }
+    }
+}
+
+#[test]
+fn move_sets_are_respected() {
+    let cube = CubieCube::SOLVED.make_niss_moves("F2 L' D B R'".parse().unwrap());
+    for set in [
+        MoveSet::eo(Axis::FB),
+        "U R2 F2".parse().unwrap(),
+        "R U'".parse().unwrap(),
+    ] {
+        for all in [false, true] {
+            let mut pruned = LinearSolver::new(cube.clone());
+            pruned.set_max_depth(6);
+            pruned.set_all(all);
+            pruned.set_move_set(set);
+            let mut blind = LinearSolver::new(cube.clone());
+            blind.prune = false;
+            blind.set_max_depth(6);
+            blind.set_all(all);
+            blind.set_move_set(set);
+
+            let sols = pruned.collect::<Vec<_>>();
+            assert!(sols.iter().flat_map(|s| &s.0).all(|&m| set.contains(m)));
+            assert_eq!(sols, blind.collect::<Vec<_>>());
+        }
+    }
+}
+
+#[test]
+fn variants_reverse_final_quarter_turns() {
+    use cube_lib::mv;
+
+    let cube = CubieCube::SOLVED.make_niss_moves("R U F".parse().unwrap());
+    let target = DrxsTarget::default();
+    let mut solver = LinearSolver::new(cube.clone());
+    solver.set_max_depth(4);
+    let sols: Vec<_> = solver.collect();
+
+    let mut solver = LinearSolver::new(cube.clone());
+    solver.set_max_depth(4);
+    solver.set_all(true);
+    let all: Vec<_> = solver.collect();
+
+    for sol in &sols {
+        let variants = quarter_turn_variants(&cube, sol, &target);
+        assert!(!variants.contains(sol));
+        for v in &variants {
+            let n = sol.0.len().saturating_sub(2);
+            assert_eq!(v.0[..n], sol.0[..n]);
+            assert!(all.contains(v));
+        }
+    }
+    // F' U ends in a quarter turn which can be reversed
+    let sol = MoveSequence(vec![mv!(F, 3), mv!(U, 1)]);
+    assert_eq!(
+        quarter_turn_variants(&cube, &sol, &target),
+        vec![MoveSequence(vec![mv!(F, 3), mv!(U, 3)])]
+    );
+}
+
+#[test]
+fn budgets_end_the_search() {
+    let cube = CubieCube::SOLVED.make_niss_moves("L F' U2 R D' B (R2 U)".parse().unwrap());
+    let mut full = LinearSolver::new(cube.clone());
+    full.set_max_depth(6);
+    let full: Vec<_> = full.collect();
+
+    let mut solver = LinearSolver::new(cube.clone());
+    solver.set_budget(Budget::new().nodes(600));
+    solver.set_max_depth(6);
+    let sols: Vec<_> = solver.by_ref().collect();
+    assert!(solver.out_of_budget());
+    assert_eq!(solver.next(), None);
+    // The solutions found are the start of the full search, and include every solution up to the
+    // finished depth
+    let finished = solver.finished_depth().unwrap();
+    assert!(finished < 6);
+    assert!(full.starts_with(&sols));
+    assert!(sols.len() >= full.iter().filter(|s| s.0.len() <= finished).count());
+
+    let token = CancelToken::new();
+    token.cancel();
+    let mut solver = LinearSolver::new(cube);
+    solver.set_budget(Budget::new().cancel_token(token));
+    solver.set_max_depth(6);
+    assert_eq!(solver.next(), None);
+    assert!(solver.out_of_budget());
+}
@@ -1,20 +1,57 @@
-use clap::Parser;
+use std::{
+    fmt::Display,
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum, error::ErrorKind};
 use cube_lib::{
-    cube333::{CubieCube, moves::Move333},
-    moves::NissSequence,
+    cube333::{CubieCube, axis::Axis, moves::Move333},
+    moves::{MoveSequence, NissSequence},
+};
+use drxs::{
+    AxisStatus, Budget, BuildError, CornerPattern, DrxsTarget, FinishBuilder, FinishGoal,
+    Histogram, HtrSubset, LinearSolverBuilder, MoveSet, PipelineBuilder, PremoveBuilder,
+    PremoveSolution, Scorer, Skeleton, SliceFlip, axis_name, drxs_cases, drxs_subset,
+    finished_cube, optimal_length, random_state,
 };
+use rand::{SeedableRng, rngs::StdRng};
+use serde_json::{Value, json};
+use table_cache::TableCache;
 
-// TODO allow specifying the axes and slice count and flipped edge count ? of output drs maybe. An
-// interface would need to be made
+mod repl;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum Format {
+    /// One solution per line
+    Text,
+    /// One JSON object per line
+    Json,
+}
 
 /// A scramble to dr-Xs solver
 #[derive(Parser, Debug)]
-#[command(version, about)]
+#[command(
+    version,
+    about,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// The scramble you wish to solve written in HTM. Niss brackets are allowed :)
-    scramble: NissSequence<Move333>,
+    #[arg(required_unless_present = "batch")]
+    scramble: Option<NissSequence<Move333>>,
 
-    /// Print solutions with final qts reversed
+    /// Solve every scramble in a file instead, one per line. Use - to read from stdin
+    #[arg(long, conflicts_with = "scramble")]
+    batch: Option<PathBuf>,
+
+    /// Print solutions with final qts reversed after each solution
     #[arg(short)]
     all: bool,
 
@@ -49,18 +86,769 @@ struct Args {
     /// Do not print the number of moves
     #[arg(short)]
     plain: bool,
+
+    /// How to print solutions
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+
+    /// Only solve the slice edges of this axis (ud, fb or lr). May be given more than once
+    #[arg(long, value_parser = drxs::parse_axis)]
+    axis: Vec<Axis>,
+
+    /// The number of slice edges left outside of the slice
+    #[arg(long, default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=4))]
+    bad_slice: u8,
+
+    /// How the slice edges in the slice are oriented (oriented, flipped or either)
+    #[arg(long, default_value = "either")]
+    flip: SliceFlip,
+
+    /// Skip solutions which are mirrors or rotations of an earlier one, for symmetric scrambles
+    #[arg(long)]
+    reduce_symmetry: bool,
+
+    /// Search on every core. Solutions are printed once every solution of their length is found
+    #[arg(long)]
+    parallel: bool,
+
+    /// Print the half turn reduction subset that each solution leads to, such as 4a1 4e. Solutions
+    /// ending with twisted corners have no subset and print -
+    #[arg(long)]
+    subset: bool,
+
+    /// Follow each solution with the shortest way on to dr or htr, to see how good the dr-xs is.
+    /// Finishes keep the dr on the axis of the dr-xs, so only a dr-xs which is already a dr can
+    /// be finished unless --finish-any-moves is given
+    #[arg(long)]
+    finish: Option<FinishGoal>,
+
+    /// The longest finish to look for
+    #[arg(long, default_value_t = 8, requires = "finish")]
+    finish_max: usize,
+
+    /// Let finishes use any move, leaving the dr on the way, so that bad slice edges can be put
+    /// back
+    #[arg(long, requires = "finish")]
+    finish_any_moves: bool,
+
+    /// Carry on from these moves, such as a skeleton found earlier, only searching for the moves
+    /// after them. Niss brackets are allowed
+    #[arg(long)]
+    prefix: Option<NissSequence<Move333>>,
+
+    /// Print the solutions from the best score to the worst, rather than as they are found
+    #[arg(long)]
+    sort: bool,
+
+    /// Only print the K best scoring solutions
+    #[arg(long, id("K"))]
+    top: Option<usize>,
+
+    /// The weights that solutions are scored with, any of length, bad_slice, twisted, corners,
+    /// bad_edges and qt, e.g. "length=1,corners=0.5". Lower scores are better
+    #[arg(long, default_value_t = Scorer::default())]
+    score: Scorer,
+
+    /// Try every premove (1) or pair of premoves (2) on the inverse first, printing solutions as
+    /// (premoves) moves. The maximum length counts the premoves
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=2), conflicts_with_all = ["niss", "min", "all", "parallel", "reduce_symmetry", "prefix"])]
+    premoves: Option<u8>,
+
+    /// Give up after searching this many move sequences
+    #[arg(long, id("NODES"))]
+    node_limit: Option<u64>,
+
+    /// Give up after searching for this many milliseconds
+    #[arg(long, id("MS"))]
+    time_limit: Option<u64>,
+
+    /// Find EO then dr-xs skeletons instead, ranked by their total length
+    #[arg(long, conflicts_with_all = ["niss", "min", "all", "moves", "reduce_symmetry", "NODES", "MS", "subset", "finish", "prefix", "premoves", "sort", "K"])]
+    pipeline: bool,
+
+    /// Finish the pipeline skeletons with a half turn reduction
+    #[arg(long, requires = "pipeline")]
+    htr: bool,
+
+    /// Only use these moves, e.g. "U D F2 B2 R L" to keep the edge orientation for F and B
+    #[arg(long)]
+    moves: Option<MoveSet>,
+
+    /// Only finish with this corner orientation (oriented, cw or acw). May be given more than once
+    #[arg(long)]
+    corners: Vec<CornerPattern>,
 }
 
-fn main() {
-    let args = Args::parse();
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print how long the optimal dr-xs solutions of random states are
+    Analyse(AnalyseArgs),
+    /// Read commands from stdin, keeping the pruning tables loaded between solves
+    Repl,
+    /// Print how close a scramble is to dr-xs on each axis
+    Describe(DescribeArgs),
+    /// Answer solve requests sent as JSON lines over TCP
+    #[cfg(feature = "server")]
+    Serve(ServeArgs),
+}
 
-    let cube = CubieCube::SOLVED.make_niss_moves(args.scramble);
+#[cfg(feature = "server")]
+#[derive(clap::Args, Debug)]
+struct ServeArgs {
+    /// The address to listen on
+    #[arg(long, default_value = "127.0.0.1:7878")]
+    addr: String,
 
-    let mut solver = drxs::LinearSolver::new(cube);
+    /// The deepest a request may search
+    #[arg(short('M'), default_value_t = 12)]
+    max: usize,
+
+    /// How many milliseconds a request may search for
+    #[arg(long, default_value_t = 10000)]
+    time_limit: u64,
+}
+
+#[derive(clap::Args, Debug)]
+struct DescribeArgs {
+    /// The scramble to describe. Niss brackets are allowed, and describe the inverse
+    scramble: NissSequence<Move333>,
+
+    /// How to print the description
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+}
+
+#[derive(clap::Args, Debug)]
+struct AnalyseArgs {
+    /// The number of random states to solve
+    #[arg(short, default_value_t = 100)]
+    num: usize,
+
+    /// The seed of the random states, so that runs can be repeated
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+
+    /// Only use states with the edges oriented for this axis (ud, fb or lr)
+    #[arg(long, value_parser = drxs::parse_axis)]
+    eo: Option<Axis>,
+
+    /// A number of slice edges left outside of the slice to compare. May be given more than once,
+    /// and defaults to every number
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=4))]
+    bad_slice: Vec<u8>,
+
+    /// Maximum solution length
+    #[arg(short('M'), default_value_t = 12)]
+    max: usize,
+}
+
+/// Prints a histogram of the optimal solution lengths of random states for each number of bad
+/// slice edges. Every number is solved from the same states.
+fn analyse(args: &AnalyseArgs) {
+    let mut rng = StdRng::seed_from_u64(args.seed);
+    let set = args.eo.map_or(MoveSet::HTM, MoveSet::eo);
+    let states: Vec<_> = (0..args.num).map(|_| random_state(&mut rng, set)).collect();
+
+    let bad_slices = if args.bad_slice.is_empty() {
+        (0..=4).collect()
+    } else {
+        args.bad_slice.clone()
+    };
+    for (i, &bad_slice) in bad_slices.iter().enumerate() {
+        let target = DrxsTarget {
+            bad_slice: bad_slice as usize,
+            ..DrxsTarget::default()
+        };
+        let mut histogram = Histogram::new();
+        for cube in &states {
+            histogram.add(optimal_length(cube, &target, args.max));
+        }
+
+        if i > 0 {
+            println!();
+        }
+        print!("{bad_slice} bad slice edges");
+        match histogram.mean() {
+            Some(mean) => println!(", mean {mean:.2} over {} states", histogram.solved()),
+            None => println!(),
+        }
+        print!("{histogram}");
+    }
+}
+
+/// Prints, for each axis, the number of flipped edges, twisted corners and slice edges in their
+/// slice, followed by the dr-xs cases the cube is in on that axis.
+fn describe(cube: &CubieCube, format: Format) {
+    let axes = Axis::AXES.map(|a| (AxisStatus::new(cube, a), drxs_cases(cube, a)));
+
+    if format == Format::Json {
+        let axes: Vec<Value> = axes
+            .iter()
+            .map(|(s, cases)| {
+                let cases: Vec<Value> = cases
+                    .iter()
+                    .map(|c| {
+                        json!({
+                            "corners": c.corners.to_string(),
+                            "bad_slice": c.bad_slice,
+                        })
+                    })
+                    .collect();
+                json!({
+                    "axis": axis_name(s.axis),
+                    "flipped_edges": s.flipped,
+                    "twisted_corners": s.twisted,
+                    "slice_edges": s.slice,
+                    "drxs": cases,
+                })
+            })
+            .collect();
+        println!("{}", json!({ "axes": axes }));
+        return;
+    }
+
+    println!("axis  eo  co  slice  dr-xs");
+    for (s, cases) in axes {
+        let cases: Vec<_> = cases
+            .iter()
+            .map(|c| format!("{} corners, {} bad slice", c.corners, c.bad_slice))
+            .collect();
+        println!(
+            "{:<4}  {:>2}  {:>2}  {:>5}  {}",
+            axis_name(s.axis),
+            s.flipped,
+            s.twisted,
+            s.slice,
+            if cases.is_empty() {
+                "-".to_string()
+            } else {
+                cases.join("; ")
+            }
+        );
+    }
+}
+
+impl Args {
+    fn builder(&self) -> LinearSolverBuilder {
+        let mut builder = LinearSolverBuilder::new()
+            .variants(self.all)
+            .niss(self.niss)
+            .parallel(self.parallel)
+            .reduce_symmetry(self.reduce_symmetry)
+            .target(self.target())
+            .move_set(self.moves.unwrap_or_default());
+
+        if let Some(d) = self.min {
+            builder = builder.min_depth(d);
+        }
+        if let Some(d) = self.max {
+            builder = builder.max_depth(d);
+        }
+        if let Some(n) = self.num {
+            builder = builder.limit(n);
+        }
+        if let Some(prefix) = &self.prefix {
+            builder = builder.prefix(prefix.clone());
+        }
+        builder.budget(self.budget())
+    }
+
+    /// The budget of a search, which starts when this is called.
+    fn budget(&self) -> Budget {
+        let mut budget = Budget::new();
+        if let Some(n) = self.node_limit {
+            budget = budget.nodes(n);
+        }
+        if let Some(ms) = self.time_limit {
+            budget = budget.time_limit(Duration::from_millis(ms));
+        }
+        budget
+    }
+
+    fn pipeline_builder(&self) -> PipelineBuilder {
+        let mut builder = PipelineBuilder::new().htr(self.htr).target(self.target());
+
+        if let Some(d) = self.max {
+            builder = builder.max_length(d);
+        }
+        builder
+    }
+
+    fn premove_builder(&self) -> PremoveBuilder {
+        let mut builder = PremoveBuilder::new()
+            .pairs(self.premoves == Some(2))
+            .target(self.target())
+            .move_set(self.moves.unwrap_or_default());
+
+        if let Some(d) = self.max {
+            builder = builder.max_depth(d);
+        }
+        builder.budget(self.budget())
+    }
+
+    fn target(&self) -> DrxsTarget {
+        let default = DrxsTarget::default();
+        DrxsTarget {
+            axes: if self.axis.is_empty() {
+                default.axes
+            } else {
+                self.axis.clone()
+            },
+            bad_slice: self.bad_slice as usize,
+            flip: self.flip,
+            corners: if self.corners.is_empty() {
+                default.corners
+            } else {
+                self.corners.clone()
+            },
+        }
+    }
+
+    /// Whether the solutions are printed in order of their score.
+    fn ranked(&self) -> bool {
+        self.sort || self.top.is_some()
+    }
 
-    args.max.inspect(|&d| solver.set_max_depth(d));
+    /// How many moves longer than optimal a solution is allowed to be, if we are only listing
+    /// solutions close to optimal.
+    fn optimal_slack(&self) -> Option<usize> {
+        self.maxfrom.or(self.opt.then_some(0))
+    }
+}
+
+/// Something the program can find for a scramble.
+trait Solution: Display {
+    fn len(&self) -> usize;
+
+    fn to_json(&self, args: &Args, scramble: &NissSequence<Move333>) -> Value;
+
+    /// The score of the solution with the weights the arguments give, see `Scorer`.
+    fn score(&self, _args: &Args, _scramble: &NissSequence<Move333>) -> f64 {
+        self.len() as f64
+    }
+
+    /// The subset the solution leads to, if it finishes with oriented corners.
+    fn subset(&self, _scramble: &NissSequence<Move333>) -> Option<HtrSubset> {
+        None
+    }
+
+    /// The shortest way on from the solution that the arguments ask for, if there is one, on the
+    /// side that the solution finishes on.
+    fn finish(
+        &self,
+        _args: &Args,
+        _scramble: &NissSequence<Move333>,
+    ) -> Option<NissSequence<Move333>> {
+        None
+    }
+}
+
+impl Solution for NissSequence<Move333> {
+    fn len(&self) -> usize {
+        self.normal.0.len() + self.inverse.0.len()
+    }
+
+    /// Describes the dr-xs the solution reaches as well as its moves.
+    fn to_json(&self, args: &Args, scramble: &NissSequence<Move333>) -> Value {
+        let info = args.target().analyse(&finished_cube(scramble, self));
+        let moves: Vec<Value> = (self.normal.0.iter().map(|m| (m, "normal")))
+            .chain(self.inverse.0.iter().map(|m| (m, "inverse")))
+            .map(|(m, side)| json!({ "move": m.to_string(), "side": side }))
+            .collect();
+        json!({
+            "solution": self.to_string(),
+            "moves": moves,
+            "length": self.len(),
+            "axis": info.map(|i| axis_name(i.axis)),
+            "corners": info.map(|i| i.corners.to_string()),
+            "bad_slice": info.map(|i| i.bad_slice),
+            "flipped": info.map(|i| i.flipped),
+            "subset": self.subset(scramble).map(|s| s.to_string()),
+            "finish": self.finish(args, scramble).map(|f| f.to_string()),
+            "score": self.score(args, scramble),
+        })
+    }
+
+    fn subset(&self, scramble: &NissSequence<Move333>) -> Option<HtrSubset> {
+        drxs_subset(&finished_cube(scramble, self))
+    }
+
+    fn score(&self, args: &Args, scramble: &NissSequence<Move333>) -> f64 {
+        let side = if self.inverse.0.is_empty() {
+            &self.normal
+        } else {
+            &self.inverse
+        };
+        let cube = finished_cube(scramble, self);
+        args.score.score(self.len(), side.0.last().copied(), &cube)
+    }
+
+    /// Carries on from the side the solution finishes on, so a solution ending on the inverse is
+    /// finished on the inverse too.
+    fn finish(
+        &self,
+        args: &Args,
+        scramble: &NissSequence<Move333>,
+    ) -> Option<NissSequence<Move333>> {
+        let finisher = FinishBuilder::new()
+            .goal(args.finish?)
+            .max_depth(args.finish_max)
+            .any_moves(args.finish_any_moves);
+        if self.inverse.0.is_empty() {
+            let start = CubieCube::SOLVED.make_niss_moves(scramble.clone());
+            let finish = finisher.after(&start, &self.normal).ok()?.next()?;
+            return Some(NissSequence {
+                normal: finish,
+                inverse: MoveSequence(vec![]),
+            });
+        }
+        // The inverse side of the scramble after the normal moves, which the inverse moves
+        // continue from
+        let mut normal = scramble.normal.clone();
+        normal.0.extend(self.normal.0.iter().copied());
+        let start = CubieCube::SOLVED.make_niss_moves(NissSequence {
+            normal: scramble.inverse.clone(),
+            inverse: normal,
+        });
+        let finish = finisher.after(&start, &self.inverse).ok()?.next()?;
+        Some(NissSequence {
+            normal: MoveSequence(vec![]),
+            inverse: finish,
+        })
+    }
+}
+
+impl Solution for PremoveSolution {
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn to_json(&self, args: &Args, scramble: &NissSequence<Move333>) -> Value {
+        let info = args.target().analyse(&self.finished_cube(scramble));
+        let moves: Vec<Value> = (self.premoves.0.iter().map(|m| (m, "inverse")))
+            .chain(self.moves.0.iter().map(|m| (m, "normal")))
+            .map(|(m, side)| json!({ "move": m.to_string(), "side": side }))
+            .collect();
+        json!({
+            "solution": self.to_string(),
+            "premoves": self.premoves.to_string(),
+            "moves": moves,
+            "length": self.len(),
+            "axis": info.map(|i| axis_name(i.axis)),
+            "corners": info.map(|i| i.corners.to_string()),
+            "bad_slice": info.map(|i| i.bad_slice),
+            "flipped": info.map(|i| i.flipped),
+            "subset": self.subset(scramble).map(|s| s.to_string()),
+            "finish": self.finish(args, scramble).map(|f| f.to_string()),
+            "score": self.score(args, scramble),
+        })
+    }
+
+    fn subset(&self, scramble: &NissSequence<Move333>) -> Option<HtrSubset> {
+        drxs_subset(&self.finished_cube(scramble))
+    }
+
+    fn score(&self, args: &Args, scramble: &NissSequence<Move333>) -> f64 {
+        let cube = self.finished_cube(scramble);
+        args.score
+            .score(self.len(), self.moves.0.last().copied(), &cube)
+    }
+
+    fn finish(
+        &self,
+        args: &Args,
+        scramble: &NissSequence<Move333>,
+    ) -> Option<NissSequence<Move333>> {
+        let finish = FinishBuilder::new()
+            .goal(args.finish?)
+            .max_depth(args.finish_max)
+            .any_moves(args.finish_any_moves)
+            .after(&self.start(scramble), &self.moves)
+            .ok()?
+            .next()?;
+        Some(NissSequence {
+            normal: finish,
+            inverse: MoveSequence(vec![]),
+        })
+    }
+}
+
+impl Solution for Skeleton {
+    fn len(&self) -> usize {
+        self.move_count()
+    }
+
+    fn to_json(&self, _: &Args, _: &NissSequence<Move333>) -> Value {
+        json!({
+            "eo": self.eo.to_string(),
+            "eo_axis": axis_name(self.eo_axis),
+            "drxs": self.drxs.to_string(),
+            "htr": self.htr.as_ref().map(|htr| htr.to_string()),
+            "length": self.move_count(),
+        })
+    }
+}
+
+fn exit_with(e: BuildError) -> ! {
+    Args::command().error(ErrorKind::ArgumentConflict, e).exit()
+}
+
+/// What was found for a scramble.
+struct Found {
+    count: usize,
+    /// The length of the shortest solution, which is optimal unless a minimum length was given.
+    shortest: Option<usize>,
+}
+
+/// Prints the solutions, or just how many there are, as asked for by the arguments. With `json`,
+/// the JSON objects of the solutions are collected there instead of printed.
+fn print_solutions<T: Solution>(
+    args: &Args,
+    scramble: &NissSequence<Move333>,
+    sols: impl Iterator<Item = T>,
+    mut json: Option<&mut Vec<Value>>,
+) -> Found {
+    let mut sols = sols.peekable();
+    let shortest = sols.peek().map(T::len);
+    let limit = match args.optimal_slack() {
+        Some(n) => shortest.unwrap_or(0) + n,
+        None => usize::MAX,
+    };
+    let sols = sols.take_while(|sol| sol.len() <= limit);
+    // Ranking needs every solution before any can be printed
+    let sols: Box<dyn Iterator<Item = T>> = if args.ranked() {
+        let mut scored: Vec<_> = sols.map(|sol| (sol.score(args, scramble), sol)).collect();
+        scored.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+        scored.truncate(args.top.unwrap_or(usize::MAX));
+        Box::new(scored.into_iter().map(|(_, sol)| sol))
+    } else {
+        Box::new(sols)
+    };
+
+    if args.count {
+        let count = sols.count();
+        if json.is_none() {
+            match args.format {
+                Format::Text => println!("{count}"),
+                Format::Json => println!("{}", json!({ "count": count })),
+            }
+        }
+        return Found { count, shortest };
+    }
+
+    let mut count = 0;
+    for sol in sols {
+        count += 1;
+        if let Some(json) = &mut json {
+            json.push(sol.to_json(args, scramble));
+        } else if args.format == Format::Json {
+            println!("{}", sol.to_json(args, scramble));
+        } else {
+            let mut line = if args.plain {
+                sol.to_string()
+            } else {
+                format!("{sol} ({})", sol.len())
+            };
+            if args.subset {
+                match sol.subset(scramble) {
+                    Some(subset) => line += &format!("  {subset}"),
+                    None => line += "  -",
+                }
+            }
+            if args.finish.is_some() {
+                match sol.finish(args, scramble) {
+                    // Solutions which already reach the goal need nothing more
+                    Some(finish) if finish.len() == 0 => line += "  + (0)",
+                    Some(finish) => line += &format!("  + {finish} ({})", finish.len()),
+                    None => line += "  + -",
+                }
+            }
+            if args.ranked() {
+                line += &format!("  score {}", sol.score(args, scramble));
+            }
+            println!("{line}");
+        }
+    }
+    Found { count, shortest }
+}
+
+/// Solves one scramble, see `print_solutions`.
+fn solve(
+    args: &Args,
+    scramble: &NissSequence<Move333>,
+    json: Option<&mut Vec<Value>>,
+) -> Result<Found, BuildError> {
+    Ok(if args.pipeline {
+        let sols = args.pipeline_builder().build(scramble.clone())?;
+        let sols = sols.take(args.num.unwrap_or(usize::MAX));
+        print_solutions(args, scramble, sols, json)
+    } else if args.premoves.is_some() {
+        let mut sols = args.premove_builder().build(scramble.clone())?;
+        let found = print_solutions(
+            args,
+            scramble,
+            sols.by_ref().take(args.num.unwrap_or(usize::MAX)),
+            json,
+        );
+        report_budget(sols.out_of_budget(), sols.finished_depth());
+        found
+    } else {
+        let mut sols = args.builder().build(scramble.clone())?;
+        let found = print_solutions(args, scramble, &mut sols, json);
+        report_budget(sols.out_of_budget(), sols.finished_depth());
+        found
+    })
+}
+
+/// Says how far a search got if it ran out of budget.
+fn report_budget(out_of_budget: bool, finished_depth: Option<usize>) {
+    if out_of_budget {
+        match finished_depth {
+            Some(d) => eprintln!("out of budget, every solution of up to {d} moves was searched"),
+            None => eprintln!("out of budget before searching any length all the way"),
+        }
+    }
+}
+
+/// Solves every scramble read from `input`. Each scramble's solutions are printed under it with
+/// how long they took, and the optimal lengths are summed up at the end. Lines which aren't
+/// scrambles are reported and skipped.
+fn solve_batch(args: &Args, input: impl BufRead) {
+    let json = args.format == Format::Json;
+    // The shortest solutions are only optimal when they may be as short as possible
+    let (shortest, shortest_key) = match args.min {
+        Some(_) => ("shortest found", "shortest"),
+        None => ("optimal", "optimal"),
+    };
+    let mut lengths = Histogram::new();
+    let (mut scrambles, mut errors) = (0, 0);
+    let start = Instant::now();
+
+    for (i, line) in input.lines().enumerate() {
+        let n = i + 1;
+        let line = line.unwrap_or_else(|e| {
+            Args::command()
+                .error(ErrorKind::Io, format!("couldn't read the scrambles: {e}"))
+                .exit()
+        });
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        scrambles += 1;
+
+        let scramble: NissSequence<Move333> = match line.parse() {
+            Ok(scramble) => scramble,
+            Err(e) => {
+                errors += 1;
+                let error = format!("couldn't parse `{line}`: {e}");
+                if json {
+                    println!("{}", json!({ "line": n, "error": error }));
+                } else {
+                    eprintln!("line {n}: {error}");
+                }
+                continue;
+            }
+        };
+
+        if !json {
+            println!("# {n}: {scramble}");
+        }
+        let mut sols = json.then(Vec::new);
+        let time = Instant::now();
+        let found = solve(args, &scramble, sols.as_mut()).unwrap_or_else(|e| exit_with(e));
+        let ms = time.elapsed().as_secs_f64() * 1000.0;
+        lengths.add(found.shortest);
+
+        if let Some(sols) = sols {
+            let mut result = json!({
+                "line": n,
+                "scramble": scramble.to_string(),
+                "count": found.count,
+                shortest_key: found.shortest,
+                "time_ms": ms,
+            });
+            if !args.count {
+                result["solutions"] = sols.into();
+            }
+            println!("{result}");
+        } else {
+            println!("# {} solutions in {ms:.2}ms", found.count);
+            println!();
+        }
+    }
+
+    let mean = lengths.mean();
+    let secs = start.elapsed().as_secs_f64();
+    if json {
+        let summary = json!({
+            "scrambles": scrambles,
+            "errors": errors,
+            "unsolved": lengths.unsolved,
+            format!("mean_{shortest_key}"): mean,
+            format!("{shortest_key}_lengths"): lengths.lengths,
+            "time_ms": secs * 1000.0,
+        });
+        println!("{}", json!({ "summary": summary }));
+        return;
+    }
+    println!(
+        "# {scrambles} scrambles in {secs:.2}s, {errors} errors, {} unsolved",
+        lengths.unsolved
+    );
+    if let Some(mean) = mean {
+        println!("# mean {shortest} length: {mean:.2}");
+    }
+    for (len, n) in lengths.lengths {
+        println!("# {shortest} length {len}: {n}");
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+    if let Some(dir) = TableCache::default_dir() {
+        drxs::cache_tables(TableCache::new(dir));
+    }
+
+    match &args.command {
+        Some(Command::Analyse(analyse_args)) => return analyse(analyse_args),
+        Some(Command::Repl) => return repl::run(io::stdin().lock()),
+        Some(Command::Describe(describe_args)) => {
+            let cube = CubieCube::SOLVED.make_niss_moves(describe_args.scramble.clone());
+            return describe(&cube, describe_args.format);
+        }
+        #[cfg(feature = "server")]
+        Some(Command::Serve(serve_args)) => {
+            let limits = drxs::server::Limits {
+                max_depth: serve_args.max,
+                time: std::time::Duration::from_millis(serve_args.time_limit),
+            };
+            let served = drxs::server::Server::bind(&serve_args.addr, limits)
+                .and_then(|server| server.run());
+            if let Err(e) = served {
+                Args::command().error(ErrorKind::Io, e).exit()
+            }
+            return;
+        }
+        None => {}
+    }
 
-    for sol in solver {
-        println!("{sol}");
+    match (&args.scramble, &args.batch) {
+        (Some(scramble), _) => {
+            solve(&args, scramble, None).unwrap_or_else(|e| exit_with(e));
+        }
+        (None, Some(path)) if path.as_os_str() == "-" => solve_batch(&args, io::stdin().lock()),
+        (None, Some(path)) => {
+            let file = File::open(path).unwrap_or_else(|e| {
+                Args::command()
+                    .error(
+                        ErrorKind::Io,
+                        format!("couldn't open {}: {e}", path.display()),
+                    )
+                    .exit()
+            });
+            solve_batch(&args, BufReader::new(file));
+        }
+        (None, None) => unreachable!("clap requires a scramble, a batch or a command"),
     }
 }
@@ -0,0 +1,138 @@
+use std::str::FromStr;
+
+use cube_lib::cube333::{
+    axis::Axis,
+    moves::{Move333, Move333Type},
+};
+
+use crate::prune::{MOVES, move_index};
+
+/// Whether a face is on axis `a`.
+fn on_axis(ty: Move333Type, a: Axis) -> bool {
+    matches!(
+        (a, ty),
+        (Axis::UD, Move333Type::U | Move333Type::D)
+            | (Axis::FB, Move333Type::F | Move333Type::B)
+            | (Axis::LR, Move333Type::R | Move333Type::L)
+    )
+}
+
+/// A set of moves that a solver is allowed to use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MoveSet {
+    /// Bit `i` is set when `MOVES[i]` is allowed.
+    mask: u32,
+}
+
+impl MoveSet {
+    /// Every move in HTM.
+    pub const HTM: MoveSet = MoveSet {
+        mask: (1 << 18) - 1,
+    };
+
+    pub fn new(moves: impl IntoIterator<Item = Move333>) -> MoveSet {
+        MoveSet {
+            mask: moves
+                .into_iter()
+                .fold(0, |mask, m| mask | 1 << move_index(m)),
+        }
+    }
+
+    /// The moves which keep the edge orientation relative to axis `a`, so every move except the
+    /// quarter turns of the two faces on that axis. For example, `eo(Axis::FB)` is
+    /// ⟨U, D, F2, B2, R, L⟩.
+    pub fn eo(a: Axis) -> MoveSet {
+        MoveSet::new(
+            MOVES
+                .into_iter()
+                .filter(|m| m.count == 2 || !on_axis(m.ty, a)),
+        )
+    }
+
+    /// The moves which keep a domino reduction on axis `a`, so the quarter turns of the two faces
+    /// on that axis and every half turn. For example, `dr(Axis::UD)` is ⟨U, D, R2, L2, F2, B2⟩.
+    pub fn dr(a: Axis) -> MoveSet {
+        MoveSet::new(
+            MOVES
+                .into_iter()
+                .filter(|m| m.count == 2 || on_axis(m.ty, a)),
+        )
+    }
+
+    pub fn contains(&self, m: Move333) -> bool {
+        self.mask & 1 << move_index(m) != 0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mask == 0
+    }
+}
+
+impl Default for MoveSet {
+    fn default() -> Self {
+        MoveSet::HTM
+    }
+}
+
+/// Parses a move set written like a group, e.g. `U D F2 B2 R L`. A face on its own allows every
+/// turn of that face, while a face with `2` or `'` only allows that one turn. Moves may be
+/// separated by spaces or commas.
+impl FromStr for MoveSet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use Move333Type as T;
+
+        let mut moves = vec![];
+        for token in s.split([' ', ',']).filter(|t| !t.is_empty()) {
+            let mut chars = token.chars();
+            let ty = match chars.next() {
+                Some('R') => T::R,
+                Some('L') => T::L,
+                Some('U') => T::U,
+                Some('D') => T::D,
+                Some('F') => T::F,
+                Some('B') => T::B,
+                _ => return Err(format!("unknown face in `{token}`")),
+            };
+            let counts: &[u8] = match chars.as_str() {
+                "" => &[1, 2, 3],
+                "1" => &[1],
+                "2" => &[2],
+                "'" | "3" => &[3],
+                _ => return Err(format!("unknown turn in `{token}`")),
+            };
+            moves.extend(counts.iter().map(|&count| Move333 { ty, count }));
+        }
+
+        let set = MoveSet::new(moves);
+        if set.is_empty() {
+            return Err("the move set is empty".to_string());
+        }
+        Ok(set)
+    }
+}
+
+#[test]
+fn parse_move_sets() {
+    let eo: MoveSet = "U D F2 B2 R L".parse().unwrap();
+    assert_eq!(eo, MoveSet::eo(Axis::FB));
+    assert_eq!("R,L,U,D,F,B".parse(), Ok(MoveSet::HTM));
+    assert_eq!("U D R2 L2 F2 B2".parse(), Ok(MoveSet::dr(Axis::UD)));
+    assert_eq!(
+        "R' U2".parse(),
+        Ok(MoveSet::new([
+            Move333 {
+                ty: Move333Type::R,
+                count: 3
+            },
+            Move333 {
+                ty: Move333Type::U,
+                count: 2
+            },
+        ]))
+    );
+    assert!("".parse::<MoveSet>().is_err());
+    assert!("R U X".parse::<MoveSet>().is_err());
+    assert!("R3'".parse::<MoveSet>().is_err());
+}
@@ -0,0 +1,299 @@
+use cube_lib::{
+    cube333::{CubieCube, moves::Move333},
+    moves::{MoveSequence, NissSequence},
+};
+
+use crate::{Budget, DrxsTarget, LinearSolver, MoveSet, first_sequence, next_sequence};
+
+/// Finds dr-xs solutions using niss with an iterator interface. Solutions start on the normal side
+/// of the scramble and may switch to the inverse side at any point, but only once.
+///
+/// Solutions are returned in order of length, and solutions of the same length are ordered by how
+/// many of their moves are on the normal side (most first). The normal moves before a switch are
+/// only pruned by how far the inverse is from being solved after them, and every sequence of them
+/// still has to be looked at, so this is much slower than `LinearSolver`.
+pub struct NissSolver {
+    scramble: NissSequence<Move333>,
+    /// The current search depth that the solver is at. This will increase over time.
+    depth: usize,
+    /// The maximim permitted search depth. The solver will terminate past this depth.
+    max_depth: usize,
+    /// The moves that solutions may use, on either side.
+    move_set: MoveSet,
+    /// Whether we are searching solutions which switch to the inverse after `normal`, rather than
+    /// solutions without a switch.
+    switched: bool,
+    /// The moves made on the normal side before switching to the inverse.
+    normal: Vec<Move333>,
+    /// The solver for the rest of the moves after `normal`. It is given a new cube for each
+    /// sequence of normal moves rather than being made again.
+    solver: LinearSolver,
+    /// Whether `solver` is searching the moves after `normal`.
+    searching: bool,
+    /// Shared with `solver`, see `LinearSolver::set_budget`.
+    budget: Budget,
+    /// The number of sequences of normal moves looked at so far.
+    nodes: u64,
+    stopped: bool,
+    /// The deepest depth which has been searched all the way through.
+    finished: Option<usize>,
+}
+
+impl NissSolver {
+    pub fn new(scramble: NissSequence<Move333>) -> NissSolver {
+        NissSolver {
+            scramble,
+            depth: 0,
+            max_depth: 20,
+            move_set: MoveSet::HTM,
+            switched: false,
+            normal: vec![],
+            solver: LinearSolver::new(CubieCube::SOLVED),
+            searching: false,
+            budget: Budget::new(),
+            nodes: 0,
+            stopped: false,
+            finished: None,
+        }
+    }
+
+    pub(crate) fn set_min_depth(&mut self, d: usize) {
+        assert!(!self.searching);
+        self.depth = d;
+    }
+
+    pub(crate) fn set_max_depth(&mut self, d: usize) {
+        self.max_depth = d;
+    }
+
+    /// See `LinearSolver::set_all`.
+    #[cfg(test)]
+    pub(crate) fn set_all(&mut self, all: bool) {
+        self.solver.set_all(all);
+    }
+
+    /// See `LinearSolver::set_target`. The target applies to whichever side a solution finishes
+    /// on.
+    pub(crate) fn set_target(&mut self, target: DrxsTarget) {
+        assert!(!self.searching);
+        self.solver.set_target(target);
+    }
+
+    /// See `LinearSolver::set_move_set`.
+    pub(crate) fn set_move_set(&mut self, set: MoveSet) {
+        assert!(!self.searching);
+        self.solver.set_move_set(set);
+        self.move_set = set;
+    }
+
+    /// See `LinearSolver::set_budget`. The budget is shared by every search of the moves after a
+    /// switch, and each sequence of normal moves uses up a node.
+    pub(crate) fn set_budget(&mut self, budget: Budget) {
+        self.solver.set_budget(budget.clone());
+        self.budget = budget;
+    }
+
+    /// See `LinearSolver::out_of_budget`.
+    pub fn out_of_budget(&self) -> bool {
+        self.stopped
+    }
+
+    /// See `LinearSolver::finished_depth`.
+    pub fn finished_depth(&self) -> Option<usize> {
+        self.finished
+    }
+
+    /// Start searching the moves following `normal`. If we have switched to the inverse, this
+    /// solves the inverse of the cube after the normal moves. Returns false if the pruning table
+    /// rules out every solution after `normal`, or if the budget has run out.
+    fn start_search(&mut self) -> bool {
+        if !self.budget.spend(self.nodes) {
+            self.stopped = true;
+            return false;
+        }
+        self.nodes += 1;
+
+        let (cube, len) = if self.switched {
+            let mut normal = self.scramble.normal.clone();
+            normal.0.extend(self.normal.iter().copied());
+            // Swapping the sides of a niss sequence inverts the cube it produces
+            let cube = CubieCube::SOLVED.make_niss_moves(NissSequence {
+                normal: self.scramble.inverse.clone(),
+                inverse: normal,
+            });
+            (cube, self.depth - self.normal.len())
+        } else {
+            let cube = CubieCube::SOLVED.make_niss_moves(self.scramble.clone());
+            (cube, self.depth)
+        };
+
+        self.solver.set_cube(cube);
+        if self.solver.lower_bound() > len {
+            return false;
+        }
+        self.solver.set_max_depth(len);
+        self.solver.set_min_depth(len);
+        true
+    }
+
+    /// Move on to the next set of normal moves, switching to the inverse one move earlier once
+    /// every sequence of normal moves of the current length has been tried. Once every switching
+    /// point at the current depth has been tried, the search moves on to the next depth.
+    fn next_normal(&mut self) {
+        let len = if self.switched {
+            self.normal.len()
+        } else {
+            self.depth
+        };
+        // Searching without a switch doesn't depend on the normal moves, so there is only one
+        // search to do there.
+        if self.switched && next_sequence(&mut self.normal, self.move_set) {
+            return;
+        }
+        self.switched = true;
+        // Some lengths may have no sequences at all in a small enough move set
+        for len in (0..len).rev() {
+            if let Some(normal) = first_sequence(len, self.move_set) {
+                self.normal = normal;
+                return;
+            }
+        }
+        self.finished = Some(self.depth);
+        self.depth += 1;
+        self.switched = false;
+    }
+}
+
+impl Iterator for NissSolver {
+    type Item = NissSequence<Move333>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.depth <= self.max_depth && !self.stopped {
+            if !self.searching {
+                if self.start_search() {
+                    self.searching = true;
+                } else if !self.stopped {
+                    self.next_normal();
+                }
+                continue;
+            }
+
+            match self.solver.next() {
+                Some(sol) if !self.switched => {
+                    return Some(NissSequence {
+                        normal: sol,
+                        inverse: MoveSequence(vec![]),
+                    });
+                }
+                Some(sol) => {
+                    return Some(NissSequence {
+                        normal: MoveSequence(self.normal.clone()),
+                        inverse: sol,
+                    });
+                }
+                None if self.solver.out_of_budget() => self.stopped = true,
+                None => {
+                    self.searching = false;
+                    self.next_normal();
+                }
+            }
+        }
+        None
+    }
+}
+
+/// The cube that a solution to `scramble` finishes on. This is the scramble followed by the
+/// solution, or its inverse if the solution ends on the inverse side.
+pub fn finished_cube(scramble: &NissSequence<Move333>, sol: &NissSequence<Move333>) -> CubieCube {
+    let mut normal = scramble.normal.clone();
+    normal.0.extend(sol.normal.0.iter().copied());
+    let mut inverse = scramble.inverse.clone();
+    inverse.0.extend(sol.inverse.0.iter().copied());
+    if sol.inverse.0.is_empty() {
+        CubieCube::SOLVED.make_niss_moves(NissSequence { normal, inverse })
+    } else {
+        // Swapping the sides of a niss sequence inverts the cube it produces
+        CubieCube::SOLVED.make_niss_moves(NissSequence {
+            normal: inverse,
+            inverse: normal,
+        })
+    }
+}
+
+#[test]
+fn niss_solutions_solve_the_right_side() {
+    let scramble: NissSequence<Move333> = "R U F D' (L2 B)".parse().unwrap();
+    let mut solver = NissSolver::new(scramble.clone());
+    solver.set_max_depth(4);
+
+    let mut inverse_only = vec![];
+    for sol in solver {
+        let cube = if sol.inverse.0.is_empty() {
+            let mut normal = scramble.normal.clone();
+            normal.0.extend(sol.normal.0.iter().copied());
+            CubieCube::SOLVED.make_niss_moves(NissSequence {
+                normal,
+                inverse: scramble.inverse.clone(),
+            })
+        } else {
+            let mut inverse = scramble.normal.clone();
+            inverse.0.extend(sol.normal.0.iter().copied());
+            let mut normal = scramble.inverse.clone();
+            normal.0.extend(sol.inverse.0.iter().copied());
+            CubieCube::SOLVED.make_niss_moves(NissSequence { normal, inverse })
+        };
+        assert!(DrxsTarget::default().is_solved(&cube), "{sol}");
+        if sol.normal.0.is_empty() {
+            inverse_only.push(sol.inverse);
+        }
+    }
+
+    let inverse_cube = CubieCube::SOLVED.make_niss_moves(NissSequence {
+        normal: scramble.inverse,
+        inverse: scramble.normal,
+    });
+    let mut linear = LinearSolver::new(inverse_cube);
+    linear.set_max_depth(4);
+    assert_eq!(inverse_only, linear.collect::<Vec<_>>());
+}
+
+#[test]
+fn niss_finds_every_solution() {
+    use crate::CanonicalSequences;
+    use std::collections::HashSet;
+
+    let scramble: NissSequence<Move333> = "R U F".parse().unwrap();
+    let target = DrxsTarget::default();
+    let mut solver = NissSolver::new(scramble.clone());
+    solver.set_max_depth(4);
+    solver.set_all(true);
+    let sols: Vec<_> = solver.collect();
+
+    // Every way to split up to 4 moves between the sides, ending on the inverse if it has moves
+    let mut expected = HashSet::new();
+    for depth in 0..=4 {
+        for normal_len in 0..=depth {
+            for normal in CanonicalSequences::new(normal_len, MoveSet::HTM) {
+                for inverse in CanonicalSequences::new(depth - normal_len, MoveSet::HTM) {
+                    let sol = NissSequence {
+                        normal: normal.clone(),
+                        inverse,
+                    };
+                    if target.is_solved(&finished_cube(&scramble, &sol)) {
+                        expected.insert(sol.to_string());
+                    }
+                }
+            }
+        }
+    }
+    assert_eq!(sols.len(), expected.len());
+    assert_eq!(
+        sols.iter()
+            .map(|sol| sol.to_string())
+            .collect::<HashSet<_>>(),
+        expected
+    );
+    // The sides never meet, so solutions ending on the same face on both sides are distinct
+    assert!(expected.contains("F R2 (R)"));
+    assert!(expected.contains("F R2 (R')"));
+}
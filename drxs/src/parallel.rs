@@ -0,0 +1,341 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver},
+    },
+    thread,
+};
+
+use cube_lib::{
+    cube333::{CubieCube, moves::Move333},
+    moves::MoveSequence,
+};
+use rayon::prelude::*;
+
+use crate::{
+    Budget, DrxsTarget, LinearSolver, MoveSet, first_sequence, last_variation, next_sequence,
+    search_key,
+};
+
+/// The number of moves the search is split by. Two moves gives a couple of hundred branches, which
+/// is plenty to keep every thread busy.
+const SPLIT_DEPTH: usize = 2;
+
+/// The solutions of a branch, and whether its budget ran out before it was finished.
+type Branch = (Vec<MoveSequence<Move333>>, bool);
+
+/// Finds the same solutions as `LinearSolver`, in the same order, but searches each depth on
+/// several threads by splitting the search by the first moves of the solutions. The solutions of a
+/// branch are returned as soon as it and every branch before it have been searched.
+pub struct ParallelSolver {
+    cube: CubieCube,
+    /// The depth that will be searched next.
+    depth: usize,
+    max_depth: usize,
+    all: bool,
+    target: DrxsTarget,
+    move_set: MoveSet,
+    /// The search of the current depth, if it has been started.
+    search: Option<DepthSearch>,
+    /// The last of the variations following the last solution returned at the current depth,
+    /// which are skipped. If it is None after a solution, the variations go on to the end of the
+    /// depth.
+    skip_to: Option<Vec<Move333>>,
+    /// Whether the rest of the current depth is made up of variations of a solution.
+    rest_skipped: bool,
+    /// Solutions of the current depth which haven't been returned yet.
+    found: VecDeque<MoveSequence<Move333>>,
+    /// Shared between the threads, see `LinearSolver::set_budget`.
+    budget: Budget,
+    stopped: bool,
+    /// The deepest depth which has been searched all the way through.
+    finished: Option<usize>,
+}
+
+/// The branches of one depth, searched in the background and collected in order.
+struct DepthSearch {
+    receiver: Receiver<(usize, Branch)>,
+    /// The branches which have been searched but not yet taken.
+    done: Vec<Option<Branch>>,
+    /// The branch to take next.
+    next: usize,
+    /// Set once the search is no longer wanted, so that the branches which haven't started are
+    /// skipped.
+    abandoned: Arc<AtomicBool>,
+}
+
+impl DepthSearch {
+    /// The next branch in the search order, waiting for it to be searched if need be. Returns None
+    /// once every branch has been taken.
+    fn next_branch(&mut self) -> Option<Branch> {
+        if self.next == self.done.len() {
+            return None;
+        }
+        while self.done[self.next].is_none() {
+            let (i, branch) = self
+                .receiver
+                .recv()
+                .expect("a branch of the search panicked");
+            self.done[i] = Some(branch);
+        }
+        self.next += 1;
+        self.done[self.next - 1].take()
+    }
+}
+
+impl Drop for DepthSearch {
+    fn drop(&mut self) {
+        self.abandoned.store(true, Ordering::Relaxed);
+    }
+}
+
+impl ParallelSolver {
+    pub(crate) fn new(cube: CubieCube) -> ParallelSolver {
+        ParallelSolver {
+            cube,
+            depth: 0,
+            max_depth: 20,
+            all: false,
+            target: DrxsTarget::default(),
+            move_set: MoveSet::HTM,
+            search: None,
+            skip_to: None,
+            rest_skipped: false,
+            found: VecDeque::new(),
+            budget: Budget::new(),
+            stopped: false,
+            finished: None,
+        }
+    }
+
+    pub(crate) fn set_min_depth(&mut self, d: usize) {
+        self.depth = d;
+    }
+
+    pub(crate) fn set_max_depth(&mut self, d: usize) {
+        self.max_depth = d;
+    }
+
+    /// See `LinearSolver::set_all`.
+    #[cfg(test)]
+    pub(crate) fn set_all(&mut self, all: bool) {
+        self.all = all;
+    }
+
+    /// See `LinearSolver::set_target`.
+    pub(crate) fn set_target(&mut self, target: DrxsTarget) {
+        self.target = target;
+    }
+
+    /// See `LinearSolver::set_move_set`.
+    pub(crate) fn set_move_set(&mut self, set: MoveSet) {
+        self.move_set = set;
+    }
+
+    /// See `LinearSolver::set_budget`. Every thread spends from the same budget.
+    pub(crate) fn set_budget(&mut self, budget: Budget) {
+        self.budget = budget;
+    }
+
+    /// See `LinearSolver::out_of_budget`.
+    pub fn out_of_budget(&self) -> bool {
+        self.stopped
+    }
+
+    /// See `LinearSolver::finished_depth`.
+    pub fn finished_depth(&self) -> Option<usize> {
+        self.finished
+    }
+
+    /// Starts searching every branch of the current depth in the background.
+    fn start_depth(&self) -> DepthSearch {
+        let d = self.depth;
+
+        let mut prefixes = vec![];
+        if let Some(mut prefix) = first_sequence(d.min(SPLIT_DEPTH), self.move_set) {
+            prefixes.push(prefix.clone());
+            while next_sequence(&mut prefix, self.move_set) {
+                prefixes.push(prefix.clone());
+            }
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        let abandoned = Arc::new(AtomicBool::new(false));
+        let search = DepthSearch {
+            receiver,
+            done: vec![None; prefixes.len()],
+            next: 0,
+            abandoned: abandoned.clone(),
+        };
+        let (cube, target, move_set, all, budget) = (
+            self.cube.clone(),
+            self.target.clone(),
+            self.move_set,
+            self.all,
+            self.budget.clone(),
+        );
+        thread::spawn(move || {
+            prefixes
+                .into_par_iter()
+                .enumerate()
+                .for_each_with(sender, |sender, (i, prefix)| {
+                    if abandoned.load(Ordering::Relaxed) {
+                        return;
+                    }
+                    let mut solver = LinearSolver::new(cube.clone());
+                    solver.set_budget(budget.clone());
+                    solver.set_target(target.clone());
+                    solver.set_move_set(move_set);
+                    solver.set_all(all);
+                    solver.set_prefix(&prefix);
+                    solver.set_max_depth(d);
+                    solver.set_min_depth(d);
+                    let sols = solver.by_ref().collect();
+                    // Nobody is waiting for the branch if the search has been dropped
+                    let _ = sender.send((i, (sols, solver.out_of_budget())));
+                });
+        });
+        search
+    }
+
+    /// Adds the solutions of the next branch to the found solutions. Each branch skips the
+    /// variations following its own solutions, but the variations following a solution can be in
+    /// the next branch, so those can only be skipped once the branches are put back together.
+    fn merge(&mut self, sols: Vec<MoveSequence<Move333>>) {
+        for sol in sols {
+            if self.rest_skipped {
+                break;
+            }
+            if !self.all
+                && self
+                    .skip_to
+                    .as_ref()
+                    .is_some_and(|last| search_key(&sol.0).le(search_key(last)))
+            {
+                // The branch skipped the variations following this solution instead of those
+                // following the last solution, so it hasn't checked the sequences in between
+                self.check_until(last_variation(&sol.0));
+                continue;
+            }
+            self.accept(sol);
+        }
+    }
+
+    /// Checks every sequence after `skip_to` up to and including `end`, or up to the end of the
+    /// depth if `end` is None, in the same way as a branch would have.
+    fn check_until(&mut self, end: Option<Vec<Move333>>) {
+        let Some(mut moves) = self.skip_to.clone() else {
+            return;
+        };
+        while !self.rest_skipped
+            && next_sequence(&mut moves, MoveSet::HTM)
+            && end
+                .as_ref()
+                .is_none_or(|end| search_key(&moves).le(search_key(end)))
+        {
+            let skipped = self
+                .skip_to
+                .as_ref()
+                .is_some_and(|last| search_key(&moves).le(search_key(last)));
+            if !skipped
+                && moves.iter().all(|&m| self.move_set.contains(m))
+                && self
+                    .target
+                    .is_solved(&moves.iter().fold(self.cube.clone(), |c, &m| c.make_move(m)))
+            {
+                self.accept(MoveSequence(moves.clone()));
+            }
+        }
+    }
+
+    /// Returns `sol` once the solutions before it have been, skipping its variations.
+    fn accept(&mut self, sol: MoveSequence<Move333>) {
+        if !self.all {
+            self.skip_to = last_variation(&sol.0);
+            // Everything left at this depth is a variation of the solution
+            self.rest_skipped = self.skip_to.is_none();
+        }
+        self.found.push_back(sol);
+    }
+}
+
+impl Iterator for ParallelSolver {
+    type Item = MoveSequence<Move333>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(sol) = self.found.pop_front() {
+                return Some(sol);
+            }
+            if self.stopped {
+                return None;
+            }
+            let Some(search) = &mut self.search else {
+                if self.depth > self.max_depth {
+                    return None;
+                }
+                self.search = Some(self.start_depth());
+                self.skip_to = None;
+                self.rest_skipped = false;
+                continue;
+            };
+            match search.next_branch() {
+                Some((sols, stopped)) => {
+                    self.merge(sols);
+                    // The solutions of later branches would leave a gap in the search order
+                    if stopped {
+                        self.stopped = true;
+                        self.search = None;
+                    }
+                }
+                None => {
+                    self.search = None;
+                    self.finished = Some(self.depth);
+                    self.depth += 1;
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn parallel_matches_serial() {
+    use cube_lib::cube333::axis::Axis;
+
+    // U R has solutions near the end of a branch whose variations spill into the next ones
+    for scramble in ["R U F", "F2 L' D B R'", "L F' U2 R D' B (R2 U)", "U R"] {
+        let cube = CubieCube::SOLVED.make_niss_moves(scramble.parse().unwrap());
+        for all in [false, true] {
+            for set in [MoveSet::HTM, MoveSet::eo(Axis::FB)] {
+                let mut serial = LinearSolver::new(cube.clone());
+                serial.set_max_depth(6);
+                serial.set_all(all);
+                serial.set_move_set(set);
+                let mut parallel = ParallelSolver::new(cube.clone());
+                parallel.set_max_depth(6);
+                parallel.set_all(all);
+                parallel.set_move_set(set);
+                assert_eq!(parallel.collect::<Vec<_>>(), serial.collect::<Vec<_>>());
+            }
+        }
+    }
+}
+
+#[test]
+fn parallel_budget_keeps_the_search_order() {
+    let cube = CubieCube::SOLVED.make_niss_moves("L F' U2 R D' B (R2 U)".parse().unwrap());
+    let mut serial = LinearSolver::new(cube.clone());
+    serial.set_max_depth(6);
+    let serial: Vec<_> = serial.collect();
+
+    let mut parallel = ParallelSolver::new(cube);
+    parallel.set_max_depth(6);
+    parallel.set_budget(Budget::new().nodes(2000));
+    let sols: Vec<_> = parallel.by_ref().collect();
+    assert!(parallel.out_of_budget());
+    // Branches after one that ran out are dropped, so the solutions are still the start of the
+    // search
+    assert!(serial.starts_with(&sols));
+}
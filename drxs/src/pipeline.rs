@@ -0,0 +1,383 @@
+use std::{collections::VecDeque, fmt};
+
+use cube_lib::{
+    cube333::{CubieCube, axis::Axis, corner::CornerTwist, edge::EdgeFlip, moves::Move333},
+    moves::{Move, MoveSequence, NissSequence},
+};
+
+use crate::{
+    BuildError, DrxsTarget, LinearSolver, MoveSet, builder::check_target, first_move,
+    following_move, is_slice_edge, prune, symmetry::axis_slice,
+};
+
+/// A solution found by a `Pipeline`: an edge orientation, a dr-xs which keeps it, and, if asked
+/// for, a half turn reduction from the dr-xs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Skeleton {
+    /// The axis that `eo` orients the edges relative to.
+    pub eo_axis: Axis,
+    pub eo: MoveSequence<Move333>,
+    pub drxs: MoveSequence<Move333>,
+    pub htr: Option<MoveSequence<Move333>>,
+}
+
+impl Skeleton {
+    /// The number of moves in every step together.
+    pub fn move_count(&self) -> usize {
+        self.eo.0.len() + self.drxs.0.len() + self.htr.as_ref().map_or(0, |htr| htr.0.len())
+    }
+}
+
+/// The steps separated by `|`.
+impl fmt::Display for Skeleton {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} | {}", self.eo, self.drxs)?;
+        if let Some(htr) = &self.htr {
+            write!(f, " | {htr}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Configures a `Pipeline`.
+#[derive(Debug, Clone)]
+pub struct PipelineBuilder {
+    max_length: usize,
+    eo_max_depth: usize,
+    drxs_max_depth: usize,
+    htr: bool,
+    htr_max_depth: usize,
+    target: DrxsTarget,
+}
+
+impl Default for PipelineBuilder {
+    fn default() -> Self {
+        PipelineBuilder {
+            max_length: 20,
+            eo_max_depth: 7,
+            drxs_max_depth: 12,
+            htr: false,
+            htr_max_depth: 12,
+            target: DrxsTarget::default(),
+        }
+    }
+}
+
+impl PipelineBuilder {
+    pub fn new() -> PipelineBuilder {
+        PipelineBuilder::default()
+    }
+
+    /// Don't return skeletons with more than `n` moves in total.
+    pub fn max_length(mut self, n: usize) -> Self {
+        self.max_length = n;
+        self
+    }
+
+    /// Don't use edge orientations longer than `d` moves.
+    pub fn eo_max_depth(mut self, d: usize) -> Self {
+        self.eo_max_depth = d;
+        self
+    }
+
+    /// Don't use dr-xs steps longer than `d` moves.
+    pub fn drxs_max_depth(mut self, d: usize) -> Self {
+        self.drxs_max_depth = d;
+        self
+    }
+
+    /// Also finish with a half turn reduction. Only the dr-xs which are full domino reductions can
+    /// be carried on to this step, so targets which also allow other dr-xs just waste time.
+    pub fn htr(mut self, htr: bool) -> Self {
+        self.htr = htr;
+        self
+    }
+
+    /// Don't use half turn reductions longer than `d` moves.
+    pub fn htr_max_depth(mut self, d: usize) -> Self {
+        self.htr_max_depth = d;
+        self
+    }
+
+    /// Only look for the dr-xs states described by `target`. A dr-xs is never looked for on the
+    /// axis of the edge orientation before it.
+    pub fn target(mut self, target: DrxsTarget) -> Self {
+        self.target = target;
+        self
+    }
+
+    /// Start searching for skeletons for `scramble`.
+    pub fn build(self, scramble: NissSequence<Move333>) -> Result<Pipeline, BuildError> {
+        check_target(&self.target)?;
+        Ok(Pipeline {
+            cube: CubieCube::SOLVED.make_niss_moves(scramble),
+            config: self,
+            length: 0,
+            eos: vec![],
+            found: VecDeque::new(),
+        })
+    }
+}
+
+/// Finds EO → dr-xs → HTR skeletons with an iterator interface. Skeletons are returned in order of
+/// their total length. The dr-xs step uses a `LinearSolver` restricted to the moves which keep the
+/// edge orientation.
+pub struct Pipeline {
+    cube: CubieCube,
+    config: PipelineBuilder,
+    /// The total length of the skeletons being searched for.
+    length: usize,
+    /// The edge orientations of each length, found as they are needed.
+    eos: Vec<Vec<(Axis, MoveSequence<Move333>)>>,
+    /// Skeletons of the current length which haven't been returned yet.
+    found: VecDeque<Skeleton>,
+}
+
+impl Pipeline {
+    fn eos(&mut self, len: usize) -> &[(Axis, MoveSequence<Move333>)] {
+        while self.eos.len() <= len {
+            let len = self.eos.len();
+            let eos = Axis::AXES
+                .into_iter()
+                .flat_map(|a| {
+                    search(
+                        &self.cube,
+                        len,
+                        MoveSet::HTM,
+                        |c| prune::eo_distances(a)[prune::eo_coord(c, a)] as usize,
+                        |c| is_eo(c, a),
+                    )
+                    .into_iter()
+                    .map(move |eo| (a, eo))
+                })
+                .collect();
+            self.eos.push(eos);
+        }
+        &self.eos[len]
+    }
+
+    /// Finds every skeleton with `self.length` moves.
+    fn find(&mut self) {
+        let config = self.config.clone();
+        let total = self.length;
+
+        for eo_len in 0..=total.min(config.eo_max_depth) {
+            for (eo_axis, eo) in self.eos(eo_len).to_vec() {
+                let target = DrxsTarget {
+                    axes: config
+                        .target
+                        .axes
+                        .iter()
+                        .copied()
+                        .filter(|&a| a != eo_axis)
+                        .collect(),
+                    ..config.target.clone()
+                };
+                if target.axes.is_empty() {
+                    continue;
+                }
+                let cube = make_moves(self.cube.clone(), &eo);
+
+                let rest = total - eo_len;
+                let drxs_lens = if config.htr { 0..=rest } else { rest..=rest };
+                let drxs_lens = drxs_lens.filter(|&len| {
+                    len <= config.drxs_max_depth
+                        && (!config.htr || rest - len <= config.htr_max_depth)
+                });
+                for drxs_len in drxs_lens {
+                    let mut solver = LinearSolver::new(cube.clone());
+                    solver.set_max_depth(drxs_len);
+                    solver.set_target(target.clone());
+                    solver.set_move_set(MoveSet::eo(eo_axis));
+                    solver.set_min_depth(drxs_len);
+
+                    for drxs in solver.filter(|drxs| !cancels(&eo, drxs)) {
+                        if !config.htr {
+                            self.found.push_back(Skeleton {
+                                eo_axis,
+                                eo: eo.clone(),
+                                drxs,
+                                htr: None,
+                            });
+                            continue;
+                        }
+
+                        let htr_len = rest - drxs_len;
+                        let cube = make_moves(cube.clone(), &drxs);
+                        // A cube can be in dr on more than one axis, and then the half turn
+                        // reductions using only half turns are found for each of them.
+                        let mut htrs: Vec<MoveSequence<Move333>> = vec![];
+                        for &a in target.axes.iter().filter(|&&a| is_dr(&cube, a)) {
+                            let found = search(
+                                &cube,
+                                htr_len,
+                                MoveSet::dr(a),
+                                |c| prune::htr_corner_distances(a)[prune::cp_coord(c)] as usize,
+                                is_htr,
+                            );
+                            for htr in found {
+                                if !cancels(&drxs, &htr) && !htrs.contains(&htr) {
+                                    htrs.push(htr);
+                                }
+                            }
+                        }
+                        self.found.extend(htrs.into_iter().map(|htr| Skeleton {
+                            eo_axis,
+                            eo: eo.clone(),
+                            drxs: drxs.clone(),
+                            htr: Some(htr),
+                        }));
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Iterator for Pipeline {
+    type Item = Skeleton;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(skeleton) = self.found.pop_front() {
+                return Some(skeleton);
+            }
+            if self.length > self.config.max_length {
+                return None;
+            }
+            self.find();
+            self.length += 1;
+        }
+    }
+}
+
+pub(crate) fn make_moves(cube: CubieCube, moves: &MoveSequence<Move333>) -> CubieCube {
+    moves.0.iter().fold(cube, |c, &m| c.make_move(m))
+}
+
+/// Whether the last move of `a` and the first move of `b` are on the same face, so that `b` after
+/// `a` could be written with fewer moves.
+pub(crate) fn cancels(a: &MoveSequence<Move333>, b: &MoveSequence<Move333>) -> bool {
+    a.0.last()
+        .zip(b.0.first())
+        .is_some_and(|(m1, m2)| m1.ty == m2.ty)
+}
+
+pub(crate) fn is_eo(c: &CubieCube, a: Axis) -> bool {
+    c.axis_eo(a).iter().all(|&f| f == EdgeFlip::Oriented)
+}
+
+/// Whether a cube is in a domino reduction on axis `a`.
+pub(crate) fn is_dr(c: &CubieCube, a: Axis) -> bool {
+    c.axis_co(a).iter().all(|&t| t == CornerTwist::Oriented)
+        && Axis::AXES
+            .into_iter()
+            .filter(|&b| b != a)
+            .all(|b| is_eo(c, b))
+        && axis_slice(a, 0).into_iter().all(|k| is_slice_edge(c, a, k))
+}
+
+/// Whether a cube can be solved with half turns.
+pub(crate) fn is_htr(c: &CubieCube) -> bool {
+    Axis::AXES.into_iter().all(|a| is_dr(c, a)) && prune::half_turn_corners()[prune::cp_coord(c)]
+}
+
+/// Finds every sequence of exactly `len` moves of `set` that takes `cube` to a state where
+/// `solved` holds, in the search order. `bound` gives a lower bound on the number of moves needed
+/// to get from a state to one where `solved` holds. Sequences whose last move isn't needed are
+/// skipped.
+pub(crate) fn search(
+    cube: &CubieCube,
+    len: usize,
+    set: MoveSet,
+    bound: impl Fn(&CubieCube) -> usize,
+    solved: impl Fn(&CubieCube) -> bool,
+) -> Vec<MoveSequence<Move333>> {
+    fn go(
+        cube: CubieCube,
+        len: usize,
+        set: MoveSet,
+        bound: &impl Fn(&CubieCube) -> usize,
+        solved: &impl Fn(&CubieCube) -> bool,
+        moves: &mut Vec<Move333>,
+        sols: &mut Vec<MoveSequence<Move333>>,
+    ) {
+        if bound(&cube) > len - moves.len() {
+            return;
+        }
+        if moves.len() == len {
+            let needed = moves
+                .last()
+                .is_none_or(|m| !solved(&cube.clone().make_move(m.inverse())));
+            if needed && solved(&cube) {
+                sols.push(MoveSequence(moves.clone()));
+            }
+            return;
+        }
+
+        let prev = moves.last().map(|m| m.ty);
+        let mut next = first_move(prev, set);
+        while let Some(m) = next {
+            moves.push(m);
+            go(
+                cube.clone().make_move(m),
+                len,
+                set,
+                bound,
+                solved,
+                moves,
+                sols,
+            );
+            moves.pop();
+            next = following_move(prev, m, set);
+        }
+    }
+
+    let mut sols = vec![];
+    go(
+        cube.clone(),
+        len,
+        set,
+        &bound,
+        &solved,
+        &mut vec![],
+        &mut sols,
+    );
+    sols
+}
+
+#[test]
+fn skeletons_solve_each_step() {
+    let scramble: NissSequence<Move333> = "R U F D' L2 B".parse().unwrap();
+    let cube = CubieCube::SOLVED.make_niss_moves(scramble.clone());
+
+    let skeletons: Vec<_> = PipelineBuilder::new()
+        .max_length(9)
+        .build(scramble.clone())
+        .unwrap()
+        .take(50)
+        .collect();
+    assert!(!skeletons.is_empty());
+    assert!(skeletons.is_sorted_by_key(|s| s.move_count()));
+    for s in &skeletons {
+        let eo = make_moves(cube.clone(), &s.eo);
+        assert!(is_eo(&eo, s.eo_axis));
+        let drxs = make_moves(eo, &s.drxs);
+        assert!(is_eo(&drxs, s.eo_axis));
+        assert!(DrxsTarget::default().is_solved(&drxs));
+        assert!(s.htr.is_none());
+    }
+
+    let skeletons: Vec<_> = PipelineBuilder::new()
+        .htr(true)
+        .build(scramble)
+        .unwrap()
+        .take(10)
+        .collect();
+    for (i, s) in skeletons.iter().enumerate() {
+        assert!(!skeletons[..i].contains(s));
+        let cube = make_moves(cube.clone(), &s.eo);
+        let cube = make_moves(cube, &s.drxs);
+        assert!(is_htr(&make_moves(cube, s.htr.as_ref().unwrap())));
+    }
+}
@@ -0,0 +1,300 @@
+//! Searching after premoves. A premove is made on the inverse side of the scramble, which is the
+//! same as making its inverse before the scramble, and can turn a scramble without a short dr-xs
+//! into one with a short dr-xs. This is how FMC solvers use niss with a premove ("skew") in mind.
+
+use std::fmt;
+
+use cube_lib::{
+    cube333::{CubieCube, moves::Move333},
+    moves::{MoveSequence, NissSequence},
+};
+
+use crate::{
+    Budget, BuildError, CanonicalSequences, DrxsTarget, LinearSolver, MoveSet,
+    builder::check_target,
+};
+
+/// A dr-xs solution found after premoves, written in niss notation as `(premoves) moves`. The
+/// dr-xs is reached on the normal side, once the premoves have been made on the inverse.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PremoveSolution {
+    pub premoves: MoveSequence<Move333>,
+    pub moves: MoveSequence<Move333>,
+}
+
+impl PremoveSolution {
+    pub fn len(&self) -> usize {
+        self.premoves.0.len() + self.moves.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The premoves on the inverse and the moves on the normal side.
+    pub fn niss(&self) -> NissSequence<Move333> {
+        NissSequence {
+            normal: self.moves.clone(),
+            inverse: self.premoves.clone(),
+        }
+    }
+
+    /// The cube that the moves start from, which is the scramble after the premoves.
+    pub fn start(&self, scramble: &NissSequence<Move333>) -> CubieCube {
+        with_premoves(scramble, &self.premoves)
+    }
+
+    /// The cube in dr-xs that the solution reaches from `scramble`.
+    pub fn finished_cube(&self, scramble: &NissSequence<Move333>) -> CubieCube {
+        (self.moves.0.iter()).fold(self.start(scramble), |c, &m| c.make_move(m))
+    }
+}
+
+impl fmt::Display for PremoveSolution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "({})", self.premoves)?;
+        if !self.moves.0.is_empty() {
+            write!(f, " {}", self.moves)?;
+        }
+        Ok(())
+    }
+}
+
+/// The cube of `scramble` with `premoves` made on its inverse side.
+fn with_premoves(scramble: &NissSequence<Move333>, premoves: &MoveSequence<Move333>) -> CubieCube {
+    let mut scramble = scramble.clone();
+    scramble.inverse.0.extend(premoves.0.iter().copied());
+    CubieCube::SOLVED.make_niss_moves(scramble)
+}
+
+/// Configures a `PremoveSolver`.
+#[derive(Debug, Clone)]
+pub struct PremoveBuilder {
+    max_depth: usize,
+    pairs: bool,
+    target: DrxsTarget,
+    move_set: MoveSet,
+    budget: Budget,
+}
+
+impl Default for PremoveBuilder {
+    fn default() -> Self {
+        PremoveBuilder {
+            max_depth: 20,
+            pairs: false,
+            target: DrxsTarget::default(),
+            move_set: MoveSet::HTM,
+            budget: Budget::new(),
+        }
+    }
+}
+
+impl PremoveBuilder {
+    pub fn new() -> PremoveBuilder {
+        PremoveBuilder::default()
+    }
+
+    /// Don't return solutions longer than `d` moves, counting the premoves.
+    pub fn max_depth(mut self, d: usize) -> Self {
+        self.max_depth = d;
+        self
+    }
+
+    /// Also try every pair of premoves, rather than only single premoves.
+    pub fn pairs(mut self, pairs: bool) -> Self {
+        self.pairs = pairs;
+        self
+    }
+
+    /// Only look for the dr-xs states described by `target`.
+    pub fn target(mut self, target: DrxsTarget) -> Self {
+        self.target = target;
+        self
+    }
+
+    /// Only use the moves in `set`, for the premoves as well.
+    pub fn move_set(mut self, set: MoveSet) -> Self {
+        self.move_set = set;
+        self
+    }
+
+    /// Give up once `budget` runs out, see `PremoveSolver::out_of_budget`.
+    pub fn budget(mut self, budget: Budget) -> Self {
+        self.budget = budget;
+        self
+    }
+
+    /// Start searching for solutions to `scramble`.
+    pub fn build(self, scramble: NissSequence<Move333>) -> Result<PremoveSolver, BuildError> {
+        check_target(&self.target)?;
+        if self.move_set.is_empty() {
+            return Err(BuildError::EmptyMoveSet);
+        }
+
+        let lengths = if self.pairs { 1..=2 } else { 1..=1 };
+        // Canonical premoves never make the same premove twice, such as R R' for nothing or R R
+        // for R2
+        let premoves = lengths
+            .flat_map(|len| CanonicalSequences::new(len, self.move_set))
+            .collect();
+        Ok(PremoveSolver {
+            scramble,
+            config: self,
+            premoves,
+            depth: 1,
+            next: 0,
+            solver: None,
+            stopped: false,
+            finished: None,
+        })
+    }
+}
+
+/// Finds dr-xs solutions after every premove (and pair of premoves, if asked for) with an
+/// iterator interface. Solutions are returned in order of their length with the premoves, and a
+/// solution is only returned if every one of its premoves is needed.
+pub struct PremoveSolver {
+    scramble: NissSequence<Move333>,
+    config: PremoveBuilder,
+    /// Every sequence of premoves that is tried.
+    premoves: Vec<MoveSequence<Move333>>,
+    /// The length of the solutions being searched for, premoves included.
+    depth: usize,
+    /// The index in `premoves` of the next premoves to search after.
+    next: usize,
+    /// The search after the current premoves, with the index of the premoves.
+    solver: Option<(usize, LinearSolver)>,
+    stopped: bool,
+    /// The deepest depth which has been searched all the way through.
+    finished: Option<usize>,
+}
+
+impl PremoveSolver {
+    /// See `LinearSolver::out_of_budget`.
+    pub fn out_of_budget(&self) -> bool {
+        self.stopped
+    }
+
+    /// See `LinearSolver::finished_depth`.
+    pub fn finished_depth(&self) -> Option<usize> {
+        self.finished
+    }
+
+    /// Whether `sol` needs all of its premoves, so that dropping any of them doesn't reach the
+    /// target with the same moves.
+    fn needs_premoves(&self, sol: &PremoveSolution) -> bool {
+        let n = sol.premoves.0.len();
+        (0..(1 << n) - 1).all(|keep: usize| {
+            let premoves = (sol.premoves.0.iter().enumerate())
+                .filter(|&(i, _)| keep >> i & 1 == 1)
+                .map(|(_, &m)| m)
+                .collect();
+            let cube = PremoveSolution {
+                premoves: MoveSequence(premoves),
+                moves: sol.moves.clone(),
+            }
+            .finished_cube(&self.scramble);
+            !self.config.target.is_solved(&cube)
+        })
+    }
+
+    /// The search for the moves after premoves `i` that makes solutions of the current depth.
+    fn make_solver(&self, i: usize) -> LinearSolver {
+        let len = self.depth - self.premoves[i].0.len();
+        let mut solver = LinearSolver::new(with_premoves(&self.scramble, &self.premoves[i]));
+        solver.set_budget(self.config.budget.clone());
+        solver.set_max_depth(len);
+        solver.set_target(self.config.target.clone());
+        solver.set_move_set(self.config.move_set);
+        solver.set_min_depth(len);
+        solver
+    }
+}
+
+impl Iterator for PremoveSolver {
+    type Item = PremoveSolution;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.depth <= self.config.max_depth && !self.stopped {
+            if let Some((i, solver)) = &mut self.solver {
+                let i = *i;
+                match solver.next() {
+                    Some(moves) => {
+                        let sol = PremoveSolution {
+                            premoves: self.premoves[i].clone(),
+                            moves,
+                        };
+                        if self.needs_premoves(&sol) {
+                            return Some(sol);
+                        }
+                    }
+                    None => {
+                        self.stopped = solver.out_of_budget();
+                        self.solver = None;
+                    }
+                }
+                continue;
+            }
+
+            if self.next == self.premoves.len() {
+                self.finished = Some(self.depth);
+                self.depth += 1;
+                self.next = 0;
+                continue;
+            }
+            let i = self.next;
+            self.next += 1;
+            if self.premoves[i].0.len() <= self.depth {
+                self.solver = Some((i, self.make_solver(i)));
+            }
+        }
+        None
+    }
+}
+
+#[test]
+fn premoves_are_needed() {
+    let scramble: NissSequence<Move333> = "R U F D' (L2 B)".parse().unwrap();
+    let target = DrxsTarget::default();
+    let sols: Vec<_> = PremoveBuilder::new()
+        .max_depth(4)
+        .pairs(true)
+        .build(scramble.clone())
+        .unwrap()
+        .collect();
+    assert!(!sols.is_empty());
+    assert!(sols.is_sorted_by_key(|sol| sol.len()));
+    for sol in &sols {
+        assert!(target.is_solved(&sol.finished_cube(&scramble)), "{sol}");
+        // Without the premoves, the moves don't reach a dr-xs
+        let mut moved = scramble.clone();
+        moved.normal.0.extend(sol.moves.0.iter().copied());
+        assert!(
+            !target.is_solved(&CubieCube::SOLVED.make_niss_moves(moved)),
+            "{sol}"
+        );
+    }
+    // Pairs of premoves are only tried in one order, and never on the same face
+    assert!(sols.iter().all(|sol| {
+        let p = &sol.premoves.0;
+        p.len() == 1 || crate::may_follow(p[0].ty, p[1].ty)
+    }));
+    let single: Vec<_> = PremoveBuilder::new()
+        .max_depth(4)
+        .build(scramble)
+        .unwrap()
+        .collect();
+    assert_eq!(
+        single,
+        sols.into_iter()
+            .filter(|sol| sol.premoves.0.len() == 1)
+            .collect::<Vec<_>>()
+    );
+
+    let sol = PremoveSolution {
+        premoves: "B".parse().unwrap(),
+        moves: "D F' U".parse().unwrap(),
+    };
+    assert_eq!(sol.to_string(), "(B) D F' U");
+    assert_eq!(sol.niss().to_string(), "D F' U (B)");
+}
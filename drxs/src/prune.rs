@@ -0,0 +1,594 @@
+//! Pruning tables giving a lower bound on the number of moves needed to reach dr-xs.
+//!
+//! For each axis we track two coordinates: the orientation of the corners relative to that axis,
+//! and the positions and orientations of the axis' slice edges. The full product of these is too
+//! big to build quickly, so instead we store the distance to dr-xs for (corner orientation, slice
+//! edge positions) and for (slice edge positions, slice edge orientations), and take the larger.
+//! The distances depend on the `DrxsTarget` being searched for, so there is a table per target.
+//! These tables can be kept on disk between runs, see `cache_tables`.
+
+use std::{
+    collections::VecDeque,
+    sync::{Mutex, OnceLock},
+};
+
+use cube_lib::cube333::{
+    CubieCube,
+    axis::Axis,
+    corner::CornerTwist,
+    edge::EdgeFlip,
+    moves::{Move333, Move333Type},
+};
+
+use table_cache::{Table, TableCache};
+
+use crate::{COS, DrxsTarget, MoveSet, axis_name, is_slice_edge, symmetry::axis_slice};
+
+/// Number of corner orientation coordinates (the last corner's twist is implied by the others).
+const CO_COUNT: usize = 2187;
+/// Number of ways to choose 4 slice edge positions out of 12.
+const POS_COUNT: usize = 495;
+/// Number of slice coordinates (positions and orientations of the 4 slice edges).
+const SLICE_COUNT: usize = POS_COUNT * 16;
+
+/// The axes in the order their tables are stored.
+const AXES: [Axis; 3] = [Axis::UD, Axis::FB, Axis::LR];
+
+/// Every move in HTM, in the order used to index the move tables.
+pub(crate) const MOVES: [Move333; 18] = {
+    use Move333Type as T;
+    const TYS: [Move333Type; 6] = [T::R, T::L, T::U, T::D, T::F, T::B];
+    let mut moves = [Move333 { ty: T::R, count: 1 }; 18];
+    let mut i = 0;
+    while i < 18 {
+        moves[i] = Move333 {
+            ty: TYS[i / 3],
+            count: (i % 3) as u8 + 1,
+        };
+        i += 1;
+    }
+    moves
+};
+
+/// The index of a move in `MOVES`.
+pub(crate) fn move_index(m: Move333) -> usize {
+    use Move333Type as T;
+    let ty = match m.ty {
+        T::R => 0,
+        T::L => 1,
+        T::U => 2,
+        T::D => 3,
+        T::F => 4,
+        T::B => 5,
+    };
+    ty * 3 + m.count as usize - 1
+}
+
+fn twist_coord(t: CornerTwist) -> usize {
+    match t {
+        CornerTwist::Oriented => 0,
+        CornerTwist::Clockwise => 1,
+        CornerTwist::AntiClockwise => 2,
+    }
+}
+
+fn co_coord(twists: [usize; 8]) -> usize {
+    twists[..7].iter().fold(0, |acc, &t| acc * 3 + t)
+}
+
+/// The twists of all 8 corners for a corner orientation coordinate.
+fn co_twists(mut co: usize) -> [usize; 8] {
+    let mut twists = [0; 8];
+    for k in (0..7).rev() {
+        twists[k] = co % 3;
+        co /= 3;
+    }
+    twists[7] = (3 - twists[..7].iter().sum::<usize>() % 3) % 3;
+    twists
+}
+
+/// Ranks of each 12 bit mask with 4 bits set, and the masks for each rank.
+fn pos_ranks() -> &'static ([u16; 4096], [u16; POS_COUNT]) {
+    static RANKS: OnceLock<([u16; 4096], [u16; POS_COUNT])> = OnceLock::new();
+    RANKS.get_or_init(|| {
+        let mut ranks = [u16::MAX; 4096];
+        let mut masks = [0; POS_COUNT];
+        let mut rank = 0;
+        for mask in 0..4096u16 {
+            if mask.count_ones() == 4 {
+                ranks[mask as usize] = rank;
+                masks[rank as usize] = mask;
+                rank += 1;
+            }
+        }
+        (ranks, masks)
+    })
+}
+
+fn mask_of(idxs: [usize; 4]) -> u16 {
+    idxs.into_iter().fold(0, |acc, k| acc | 1 << k)
+}
+
+/// Encodes which positions hold slice edges and whether each of those is flipped.
+fn encode_slice(slice: [bool; 12], flips: [bool; 12]) -> usize {
+    let mut mask = 0;
+    let mut bits = 0;
+    for k in (0..12).filter(|&k| slice[k]) {
+        mask |= 1 << k;
+        bits = bits << 1 | flips[k] as usize;
+    }
+    pos_ranks().0[mask] as usize * 16 + bits
+}
+
+fn decode_slice(s: usize) -> ([bool; 12], [bool; 12]) {
+    let mask = pos_ranks().1[s / 16];
+    let mut slice = [false; 12];
+    let mut flips = [false; 12];
+    let mut bit = 4;
+    for k in 0..12 {
+        if mask & 1 << k != 0 {
+            bit -= 1;
+            slice[k] = true;
+            flips[k] = s & 1 << bit != 0;
+        }
+    }
+    (slice, flips)
+}
+
+fn slice_coord(c: &CubieCube, a: Axis) -> usize {
+    let eo = c.axis_eo(a);
+    encode_slice(
+        std::array::from_fn(|k| is_slice_edge(c, a, k)),
+        eo.map(|f| f == EdgeFlip::Flipped),
+    )
+}
+
+/// How a move acts on the pieces of the cube, relative to some axis: for each position, which
+/// position the piece there came from and how much its orientation changed.
+struct PieceMove {
+    corners: [(usize, usize); 8],
+    edges: [(usize, bool); 12],
+}
+
+impl PieceMove {
+    fn new(m: Move333, a: Axis) -> PieceMove {
+        let moved = CubieCube::SOLVED.make_move(m);
+        let co = moved.axis_co(a);
+        let eo = moved.axis_eo(a);
+        let solved = CubieCube::SOLVED;
+        PieceMove {
+            corners: std::array::from_fn(|k| {
+                let from = solved.cp.iter().position(|&c| c == moved.cp[k]).unwrap();
+                (from, twist_coord(co[k]))
+            }),
+            edges: std::array::from_fn(|k| {
+                let from = solved.ep.iter().position(|&e| e == moved.ep[k]).unwrap();
+                (from, eo[k] == EdgeFlip::Flipped)
+            }),
+        }
+    }
+}
+
+/// Builds the corner orientation and slice move tables for an axis.
+fn move_tables(a: Axis) -> (Vec<[u16; 18]>, Vec<[u16; 18]>) {
+    let piece_moves = MOVES.map(|m| PieceMove::new(m, a));
+
+    let co_move = (0..CO_COUNT)
+        .map(|co| {
+            let twists = co_twists(co);
+            std::array::from_fn(|i| {
+                let pm = &piece_moves[i];
+                co_coord(pm.corners.map(|(from, twist)| (twists[from] + twist) % 3)) as u16
+            })
+        })
+        .collect();
+
+    let slice_move = (0..SLICE_COUNT)
+        .map(|s| {
+            let (slice, flips) = decode_slice(s);
+            std::array::from_fn(|i| {
+                let pm = &piece_moves[i];
+                encode_slice(
+                    pm.edges.map(|(from, _)| slice[from]),
+                    pm.edges.map(|(from, flip)| flips[from] ^ flip),
+                ) as u16
+            })
+        })
+        .collect();
+
+    (co_move, slice_move)
+}
+
+/// Breadth first search from every goal coordinate, giving the distance from each coordinate to
+/// the nearest goal.
+fn distance_table(
+    size: usize,
+    goals: impl IntoIterator<Item = usize>,
+    next: impl Fn(usize, usize) -> usize,
+) -> Vec<u8> {
+    let mut dist = vec![u8::MAX; size];
+    let mut queue = VecDeque::new();
+
+    for c in goals {
+        dist[c] = 0;
+        queue.push_back(c);
+    }
+
+    while let Some(c) = queue.pop_front() {
+        for i in 0..MOVES.len() {
+            let n = next(c, i);
+            if dist[n] == u8::MAX {
+                dist[n] = dist[c] + 1;
+                queue.push_back(n);
+            }
+        }
+    }
+
+    dist
+}
+
+/// The coordinates of a cube for one axis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct AxisCoord {
+    co: u16,
+    slice: u16,
+}
+
+/// Move tables for the coordinates of one axis.
+struct AxisMoves {
+    co_move: Vec<[u16; 18]>,
+    slice_move: Vec<[u16; 18]>,
+}
+
+/// The move tables for every axis, which are built the first time they are needed.
+fn axis_moves() -> &'static [AxisMoves; 3] {
+    static MOVE_TABLES: OnceLock<[AxisMoves; 3]> = OnceLock::new();
+    MOVE_TABLES.get_or_init(|| {
+        AXES.map(|a| {
+            let (co_move, slice_move) = move_tables(a);
+            AxisMoves {
+                co_move,
+                slice_move,
+            }
+        })
+    })
+}
+
+/// The version of the distance tables kept in a `TableCache`, which has to go up whenever the
+/// way they are built changes.
+const TABLE_VERSION: u32 = 1;
+
+/// Where the distance tables are kept between runs, see `cache_tables`.
+static CACHE: OnceLock<TableCache> = OnceLock::new();
+
+/// Keep the distance tables of every target in `cache`, so that each is only built once. This
+/// has to be called before any solver is made, and only the first call does anything.
+pub fn cache_tables(cache: TableCache) {
+    let _ = CACHE.set(cache);
+}
+
+/// The distance table `name`, from the cache if there is one.
+fn cached(name: &str, len: usize, build: impl FnOnce() -> Vec<u8>) -> Table {
+    match CACHE.get() {
+        Some(cache) => cache.get(name, TABLE_VERSION, len, build),
+        None => Table::from(build()),
+    }
+}
+
+struct AxisTable {
+    /// Distances indexed by `co * POS_COUNT + slice / 16`.
+    co_pos_dist: Table,
+    /// Distances indexed by the slice coordinate.
+    slice_dist: Table,
+}
+
+impl AxisTable {
+    fn new(a: Axis, moves: &AxisMoves, target: &DrxsTarget) -> AxisTable {
+        // The corner orientations we can finish with, and the slice each one needs.
+        let goals = if target.axes.contains(&a) {
+            target
+                .corners
+                .iter()
+                .map(|&p| {
+                    let j = p as usize;
+                    (co_coord(COS[j].map(twist_coord)), axis_slice(a, j))
+                })
+                .collect()
+        } else {
+            vec![]
+        };
+
+        // The tables only depend on the corners and the slice the axis finishes with
+        let mut corners = match goals.is_empty() {
+            true => vec![],
+            false => target.corners.clone(),
+        };
+        corners.sort_by_key(|&p| p as usize);
+        corners.dedup();
+        let corners: Vec<_> = corners.iter().map(|p| p.to_string()).collect();
+        let name = match corners.is_empty() {
+            true => format!("drxs-{}-none", axis_name(a)),
+            false => format!(
+                "drxs-{}-{}-{}",
+                axis_name(a),
+                corners.join("+"),
+                target.bad_slice
+            ),
+        };
+
+        let co_pos_dist = cached(&format!("{name}-co"), CO_COUNT * POS_COUNT, || {
+            distance_table(
+                CO_COUNT * POS_COUNT,
+                goals.iter().flat_map(|&(co, idxs)| {
+                    (0..POS_COUNT)
+                        .filter(move |&pos| {
+                            (pos_ranks().1[pos] & mask_of(idxs)).count_ones() as usize
+                                + target.bad_slice
+                                == 4
+                        })
+                        .map(move |pos| co * POS_COUNT + pos)
+                }),
+                |c, i| {
+                    let (co, pos) = (c / POS_COUNT, c % POS_COUNT);
+                    moves.co_move[co][i] as usize * POS_COUNT
+                        + moves.slice_move[pos * 16][i] as usize / 16
+                },
+            )
+        });
+        let slice_dist = cached(
+            &format!("{name}-{}-slice", target.flip),
+            SLICE_COUNT,
+            || {
+                distance_table(
+                    SLICE_COUNT,
+                    (0..SLICE_COUNT).filter(|&s| {
+                        let (slice, flips) = decode_slice(s);
+                        goals
+                            .iter()
+                            .any(|&(_, idxs)| target.slice_matches(slice, flips, idxs))
+                    }),
+                    |s, i| moves.slice_move[s][i] as usize,
+                )
+            },
+        );
+
+        AxisTable {
+            co_pos_dist,
+            slice_dist,
+        }
+    }
+
+    fn lower_bound(&self, c: AxisCoord) -> u8 {
+        let (co, slice) = (c.co as usize, c.slice as usize);
+        self.co_pos_dist[co * POS_COUNT + slice / 16].max(self.slice_dist[slice])
+    }
+}
+
+/// Move and distance tables for all three axes.
+pub(crate) struct PruningTable {
+    moves: &'static [AxisMoves; 3],
+    axes: [AxisTable; 3],
+}
+
+impl PruningTable {
+    /// The pruning table for a target, which is built the first time it is needed.
+    pub(crate) fn get(target: &DrxsTarget) -> &'static PruningTable {
+        // Very few different targets get used in one run, so we keep every table around.
+        static TABLES: Mutex<Vec<(DrxsTarget, &'static PruningTable)>> = Mutex::new(Vec::new());
+
+        let mut tables = TABLES.lock().unwrap();
+        if let Some(&(_, table)) = tables.iter().find(|(t, _)| t == target) {
+            return table;
+        }
+
+        let moves = axis_moves();
+        let table: &'static PruningTable = Box::leak(Box::new(PruningTable {
+            moves,
+            axes: std::array::from_fn(|a| AxisTable::new(AXES[a], &moves[a], target)),
+        }));
+        tables.push((target.clone(), table));
+        table
+    }
+
+    pub(crate) fn coords(&self, c: &CubieCube) -> [AxisCoord; 3] {
+        AXES.map(|a| AxisCoord {
+            co: co_coord(c.axis_co(a).map(twist_coord)) as u16,
+            slice: slice_coord(c, a) as u16,
+        })
+    }
+
+    pub(crate) fn make_move(&self, coords: [AxisCoord; 3], m: Move333) -> [AxisCoord; 3] {
+        let i = move_index(m);
+        std::array::from_fn(|a| AxisCoord {
+            co: self.moves[a].co_move[coords[a].co as usize][i],
+            slice: self.moves[a].slice_move[coords[a].slice as usize][i],
+        })
+    }
+
+    /// A lower bound on the number of moves needed to reach the target.
+    pub(crate) fn lower_bound(&self, coords: [AxisCoord; 3]) -> usize {
+        self.axes
+            .iter()
+            .zip(coords)
+            .map(|(t, c)| t.lower_bound(c))
+            .min()
+            .unwrap() as usize
+    }
+
+    /// A lower bound on the number of moves needed to reach the target on axis `a`.
+    pub(crate) fn axis_lower_bound(&self, coords: [AxisCoord; 3], a: Axis) -> usize {
+        let i = AXES.iter().position(|&b| b == a).unwrap();
+        self.axes[i].lower_bound(coords[i]) as usize
+    }
+}
+
+/// Number of edge orientation coordinates (the last edge's flip is implied by the others).
+const EO_COUNT: usize = 2048;
+/// Number of corner permutations.
+const CP_COUNT: usize = 40320;
+
+/// The edge orientation coordinate of a cube relative to axis `a`.
+pub(crate) fn eo_coord(c: &CubieCube, a: Axis) -> usize {
+    c.axis_eo(a)[..11]
+        .iter()
+        .fold(0, |acc, &f| acc * 2 + (f == EdgeFlip::Flipped) as usize)
+}
+
+fn eo_flips(eo: usize) -> [bool; 12] {
+    std::array::from_fn(|k| match k {
+        11 => eo.count_ones() % 2 == 1,
+        _ => eo >> (10 - k) & 1 == 1,
+    })
+}
+
+/// The distances to oriented edges relative to each axis, indexed by `eo_coord`.
+pub(crate) fn eo_distances(a: Axis) -> &'static [u8] {
+    static TABLES: OnceLock<[Vec<u8>; 3]> = OnceLock::new();
+    let tables = TABLES.get_or_init(|| {
+        AXES.map(|a| {
+            let piece_moves = MOVES.map(|m| PieceMove::new(m, a));
+            distance_table(EO_COUNT, [0], |eo, i| {
+                let flips = eo_flips(eo);
+                piece_moves[i]
+                    .edges
+                    .map(|(from, flip)| flips[from] ^ flip)
+                    .iter()
+                    .take(11)
+                    .fold(0, |acc, &f| acc * 2 + f as usize)
+            })
+        })
+    });
+    &tables[AXES.iter().position(|&b| b == a).unwrap()]
+}
+
+/// The rank of a permutation of the 8 corners, where `perm[k]` is the solved position of the
+/// corner at position `k`.
+pub(crate) fn perm_coord(perm: [usize; 8]) -> usize {
+    (0..8).fold(0, |acc, k| {
+        acc * (8 - k) + perm[k + 1..].iter().filter(|&&p| p < perm[k]).count()
+    })
+}
+
+pub(crate) fn coord_perm(mut cp: usize) -> [usize; 8] {
+    let mut lehmer = [0; 8];
+    for k in (0..8).rev() {
+        lehmer[k] = cp % (8 - k);
+        cp /= 8 - k;
+    }
+    let mut left: Vec<usize> = (0..8).collect();
+    lehmer.map(|l| left.remove(l))
+}
+
+/// The corner permutation coordinate of a cube.
+pub(crate) fn cp_coord(c: &CubieCube) -> usize {
+    let solved = CubieCube::SOLVED;
+    perm_coord(c.cp.map(|p| solved.cp.iter().position(|&q| q == p).unwrap()))
+}
+
+/// The corner permutations that can be solved with half turns, as a lookup by `cp_coord`.
+pub(crate) fn half_turn_corners() -> &'static [bool] {
+    static TABLE: OnceLock<Vec<bool>> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let piece_moves = MOVES.map(|m| PieceMove::new(m, Axis::UD));
+        let dist = distance_table(CP_COUNT, [0], |cp, i| {
+            if MOVES[i].count != 2 {
+                // Staying put doesn't add anything to the search
+                return cp;
+            }
+            let perm = coord_perm(cp);
+            perm_coord(piece_moves[i].corners.map(|(from, _)| perm[from]))
+        });
+        dist.into_iter().map(|d| d != u8::MAX).collect()
+    })
+}
+
+/// The distances from each corner permutation to one that can be solved with half turns, using
+/// only the moves that keep a domino reduction on axis `a`. Indexed by `cp_coord`.
+pub(crate) fn htr_corner_distances(a: Axis) -> &'static [u8] {
+    static TABLES: OnceLock<[Vec<u8>; 3]> = OnceLock::new();
+    let tables = TABLES.get_or_init(|| {
+        let piece_moves = MOVES.map(|m| PieceMove::new(m, Axis::UD));
+        let goals = half_turn_corners();
+        AXES.map(|a| {
+            let set = MoveSet::dr(a);
+            distance_table(CP_COUNT, (0..CP_COUNT).filter(|&cp| goals[cp]), |cp, i| {
+                if !set.contains(MOVES[i]) {
+                    return cp;
+                }
+                let perm = coord_perm(cp);
+                perm_coord(piece_moves[i].corners.map(|(from, _)| perm[from]))
+            })
+        })
+    });
+    &tables[AXES.iter().position(|&b| b == a).unwrap()]
+}
+
+/// The fewest quarter turns of U and D which take each corner permutation to one that can be
+/// solved with half turns, with any number of half turns between them. Indexed by `cp_coord`.
+pub(crate) fn htr_quarter_turns() -> &'static [u8] {
+    static TABLE: OnceLock<Vec<u8>> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let piece_moves = MOVES.map(|m| PieceMove::new(m, Axis::UD));
+        let set = MoveSet::dr(Axis::UD);
+        let mut dist = vec![u8::MAX; CP_COUNT];
+        let mut queue = VecDeque::new();
+        for (cp, &goal) in half_turn_corners().iter().enumerate() {
+            if goal {
+                dist[cp] = 0;
+                queue.push_back(cp);
+            }
+        }
+
+        // Half turns are free, so what they reach goes to the front of the queue
+        while let Some(cp) = queue.pop_front() {
+            let perm = coord_perm(cp);
+            for (i, m) in MOVES.into_iter().enumerate() {
+                if !set.contains(m) {
+                    continue;
+                }
+                let cost = (m.count != 2) as u8;
+                let n = perm_coord(piece_moves[i].corners.map(|(from, _)| perm[from]));
+                if dist[cp] + cost < dist[n] {
+                    dist[n] = dist[cp] + cost;
+                    if cost == 0 {
+                        queue.push_front(n);
+                    } else {
+                        queue.push_back(n);
+                    }
+                }
+            }
+        }
+        dist
+    })
+}
+
+#[test]
+fn move_tables_match_cubes() {
+    let table = PruningTable::get(&DrxsTarget::default());
+    let mut cube = CubieCube::SOLVED;
+    let mut coords = table.coords(&cube);
+    for i in 0..200 {
+        let m = MOVES[(i * 7 + i / 5) % 18];
+        cube = cube.make_move(m);
+        coords = table.make_move(coords, m);
+        assert_eq!(coords, table.coords(&cube));
+    }
+}
+
+#[test]
+fn step_tables() {
+    for cp in [0, 1, 5039, 12345, CP_COUNT - 1] {
+        assert_eq!(perm_coord(coord_perm(cp)), cp);
+    }
+    assert_eq!(half_turn_corners().iter().filter(|&&h| h).count(), 96);
+    assert_eq!(htr_quarter_turns().iter().max(), Some(&5));
+
+    let mut cube = CubieCube::SOLVED;
+    for i in 0..50 {
+        cube = cube.make_move(MOVES[(i * 5 + i / 3) % 18]);
+        for a in AXES {
+            let oriented = cube.axis_eo(a).iter().all(|&f| f == EdgeFlip::Oriented);
+            assert_eq!(eo_distances(a)[eo_coord(&cube, a)] == 0, oriented);
+        }
+    }
+}
@@ -0,0 +1,140 @@
+//! An interactive mode which keeps the pruning tables in memory between solves. Commands are read
+//! one per line:
+//!
+//! - `scramble <moves>` starts again from a new scramble.
+//! - `solve [options]` solves the current position, taking the same options as the command line.
+//! - `apply <moves>` carries on from the current position with some moves, e.g. the start of a
+//!   solution.
+//! - `undo` takes back the last `apply`.
+//! - `show` prints the position and how close it is to dr-xs on each axis.
+
+use std::io::{self, BufRead, IsTerminal, Write};
+
+use clap::Parser;
+use cube_lib::{
+    cube333::moves::Move333,
+    moves::{MoveSequence, NissSequence},
+};
+use drxs::finished_cube;
+
+use crate::{Args, Format, describe, solve};
+
+const HELP: &str = "\
+scramble <moves>   start again from a new scramble
+solve [options]    solve the current position, e.g. solve -n 10 -M 9
+apply <moves>      carry on from the current position with some moves
+undo               take back the last apply
+show               print the position and its edge, corner and slice status
+help               print this
+quit               leave";
+
+fn empty() -> NissSequence<Move333> {
+    NissSequence {
+        normal: MoveSequence(vec![]),
+        inverse: MoveSequence(vec![]),
+    }
+}
+
+/// The scramble and the moves applied to it since.
+struct State {
+    scramble: NissSequence<Move333>,
+    applied: Vec<NissSequence<Move333>>,
+}
+
+impl State {
+    /// The scramble followed by every applied move, on the side they were applied to.
+    fn moves(&self) -> NissSequence<Move333> {
+        let mut moves = empty();
+        for seq in self.applied.iter() {
+            moves.normal.0.extend(seq.normal.0.iter().copied());
+            moves.inverse.0.extend(seq.inverse.0.iter().copied());
+        }
+        moves
+    }
+
+    fn position(&self) -> NissSequence<Move333> {
+        let moves = self.moves();
+        let mut position = self.scramble.clone();
+        position.normal.0.extend(moves.normal.0);
+        position.inverse.0.extend(moves.inverse.0);
+        position
+    }
+
+    fn solve(&self, options: &str) {
+        let position = self.position().to_string();
+        let argv = ["solve"]
+            .into_iter()
+            .chain(options.split_whitespace())
+            .chain([position.as_str()]);
+        let args = match Args::try_parse_from(argv) {
+            Ok(args) => args,
+            Err(e) => {
+                let _ = e.print();
+                return;
+            }
+        };
+        if let Err(e) = solve(&args, args.scramble.as_ref().unwrap(), None) {
+            println!("error: {e}");
+        }
+    }
+
+    /// Prints the position and describes it, see `describe`. The cube is looked at from the side
+    /// the last moves were applied to.
+    fn show(&self) {
+        println!("scramble: {}", self.scramble);
+        println!("applied: {}", self.moves());
+
+        describe(&finished_cube(&self.scramble, &self.moves()), Format::Text);
+    }
+}
+
+/// Reads commands from `input` until it ends or `quit` is given.
+pub(crate) fn run(input: impl BufRead) {
+    // Only prompt when someone is typing
+    let prompt = io::stdin().is_terminal();
+    let mut state = State {
+        scramble: empty(),
+        applied: vec![],
+    };
+    let mut lines = input.lines();
+
+    loop {
+        if prompt {
+            print!("drxs> ");
+            let _ = io::stdout().flush();
+        }
+        let Some(Ok(line)) = lines.next() else {
+            break;
+        };
+        let line = line.trim();
+        let (command, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let rest = rest.trim();
+
+        match command {
+            "" => {}
+            "scramble" => match rest.parse() {
+                Ok(scramble) => {
+                    state = State {
+                        scramble,
+                        applied: vec![],
+                    }
+                }
+                Err(e) => println!("error: couldn't parse `{rest}`: {e}"),
+            },
+            "solve" => state.solve(rest),
+            "apply" => match rest.parse() {
+                Ok(moves) => state.applied.push(moves),
+                Err(e) => println!("error: couldn't parse `{rest}`: {e}"),
+            },
+            "undo" => {
+                if state.applied.pop().is_none() {
+                    println!("error: there is nothing to undo");
+                }
+            }
+            "show" => state.show(),
+            "help" => println!("{HELP}"),
+            "quit" | "exit" => break,
+            _ => println!("error: unknown command `{command}`, try help"),
+        }
+    }
+}
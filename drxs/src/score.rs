@@ -0,0 +1,141 @@
+//! Ranking solutions by more than their length. Two dr-xs of the same length can be very
+//! different to carry on from, so a `Scorer` adds up weighted properties of the state that a
+//! solution reaches, and lower scores are better.
+
+use std::{fmt, str::FromStr};
+
+use cube_lib::cube333::{CubieCube, axis::Axis, moves::Move333};
+
+use crate::{CornerPattern, drxs_cases, htr_subset};
+
+/// The weights of the properties that make up a score. Negative weights make a property a bonus.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Scorer {
+    /// For each move of the solution.
+    pub length: f64,
+    /// For each slice edge left outside of the slice.
+    pub bad_slice: f64,
+    /// For a dr-xs with twisted corners, which have to be oriented later.
+    pub twisted: f64,
+    /// For each quarter turn the corners need to reach a half turn reduction, see
+    /// `HtrSubset::quarter_turns`. Only a full dr has a subset, so only a dr scores this.
+    pub corners: f64,
+    /// For each edge left in the wrong slice for a half turn reduction, see
+    /// `HtrSubset::bad_edges`. Like `corners`, only a dr scores this.
+    pub bad_edges: f64,
+    /// For ending with a quarter turn, which can be turned the other way for a second dr-xs.
+    pub quarter_turn: f64,
+}
+
+impl Default for Scorer {
+    fn default() -> Self {
+        Scorer {
+            length: 1.0,
+            bad_slice: 1.0,
+            twisted: 1.0,
+            corners: 0.5,
+            bad_edges: 0.25,
+            quarter_turn: -0.5,
+        }
+    }
+}
+
+impl Scorer {
+    /// The score of a solution with `len` moves, whose moves on the side it finishes on end with
+    /// `last`, reaching `cube`. A cube in more than one dr-xs is scored by the best of them.
+    pub fn score(&self, len: usize, last: Option<Move333>, cube: &CubieCube) -> f64 {
+        let drxs = Axis::AXES
+            .into_iter()
+            .flat_map(|a| drxs_cases(cube, a))
+            .map(|info| {
+                let twisted = info.corners != CornerPattern::Oriented;
+                info.bad_slice as f64 * self.bad_slice + twisted as u8 as f64 * self.twisted
+            })
+            .min_by(f64::total_cmp)
+            .unwrap_or(0.0);
+        let subset = htr_subset(cube).map_or(0.0, |s| {
+            s.quarter_turns as f64 * self.corners + s.bad_edges as f64 * self.bad_edges
+        });
+        let quarter_turn = last.is_some_and(|m| m.count != 2) as u8 as f64 * self.quarter_turn;
+        len as f64 * self.length + drxs + subset + quarter_turn
+    }
+}
+
+/// The weights written like `length=1,bad_slice=1,twisted=1,corners=0.5,bad_edges=0.25,qt=-0.5`.
+impl fmt::Display for Scorer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "length={},bad_slice={},twisted={},corners={},bad_edges={},qt={}",
+            self.length,
+            self.bad_slice,
+            self.twisted,
+            self.corners,
+            self.bad_edges,
+            self.quarter_turn
+        )
+    }
+}
+
+/// Parses weights written as in `Display`, separated by commas or spaces. Weights which aren't
+/// given keep their default.
+impl FromStr for Scorer {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut scorer = Scorer::default();
+        for token in s.split([' ', ',']).filter(|t| !t.is_empty()) {
+            let (name, weight) = token
+                .split_once('=')
+                .ok_or_else(|| format!("expected a weight like `length=1`, not `{token}`"))?;
+            let weight: f64 = weight
+                .parse()
+                .map_err(|_| format!("`{weight}` isn't a number"))?;
+            let field = match name {
+                "length" => &mut scorer.length,
+                "bad_slice" => &mut scorer.bad_slice,
+                "twisted" => &mut scorer.twisted,
+                "corners" => &mut scorer.corners,
+                "bad_edges" => &mut scorer.bad_edges,
+                "qt" => &mut scorer.quarter_turn,
+                _ => {
+                    return Err(format!(
+                        "unknown weight `{name}`, expected one of length, bad_slice, twisted, corners, bad_edges or qt"
+                    ));
+                }
+            };
+            *field = weight;
+        }
+        Ok(scorer)
+    }
+}
+
+#[test]
+fn scores() {
+    let score = |scorer: &Scorer, moves: &str| {
+        let moves: cube_lib::moves::MoveSequence<Move333> = moves.parse().unwrap();
+        let cube = (moves.0.iter()).fold(CubieCube::SOLVED, |c, &m| c.make_move(m));
+        scorer.score(moves.0.len(), moves.0.last().copied(), &cube)
+    };
+    let scorer = Scorer::default();
+
+    assert_eq!(score(&scorer, ""), 0.0);
+    // A dr in 4a1 4e, which ends with a quarter turn
+    assert_eq!(score(&scorer, "U"), 1.0 + 0.5 + 1.0 - 0.5);
+    assert_eq!(score(&scorer, "R2 U2"), 2.0);
+    // Only the length and the quarter turn count for a cube which isn't in a dr-xs
+    assert_eq!(score(&scorer, "R U"), 2.0 - 0.5);
+    // B' R' solves R U F to a dr-xs on ud with two bad slice edges and twisted corners
+    let cube = CubieCube::SOLVED.make_niss_moves("R U F B' R'".parse().unwrap());
+    let last = cube_lib::mv!(R, 3);
+    assert_eq!(scorer.score(2, Some(last), &cube), 2.0 + 2.0 + 1.0 - 0.5);
+
+    let length_only: Scorer = "bad_slice=0 twisted=0,corners=0,bad_edges=0,qt=0"
+        .parse()
+        .unwrap();
+    assert_eq!(length_only.score(2, Some(last), &cube), 2.0);
+    assert_eq!(scorer.to_string().parse(), Ok(scorer));
+    assert!("length".parse::<Scorer>().is_err());
+    assert!("size=1".parse::<Scorer>().is_err());
+    assert!("length=x".parse::<Scorer>().is_err());
+}
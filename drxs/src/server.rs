@@ -0,0 +1,326 @@
+//! A line based TCP service for solving scrambles, enabled with the `server` feature.
+//!
+//! Each line a client sends is a request, a JSON object such as
+//! `{"scramble": "R U F", "max_depth": 8, "niss": true}`. The server answers with one JSON object
+//! per line: one for each solution as it is found, and then either `{"done": true, ...}` or
+//! `{"error": "..."}`. A connection may send any number of requests, which are answered in turn.
+
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use cube_lib::{cube333::moves::Move333, moves::NissSequence};
+use serde_json::{Value, json};
+
+use crate::{
+    Budget, CancelToken, DrxsTarget, LinearSolverBuilder, MoveSet, axis_name, finished_cube,
+};
+
+/// The most that a single request is allowed to ask of the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// The deepest a request may search. Requests which don't give a maximum depth search this
+    /// deep.
+    pub max_depth: usize,
+    /// How long a request may search for. The solutions found by then are kept, and the response
+    /// says that it timed out.
+    pub time: Duration,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_depth: 12,
+            time: Duration::from_secs(10),
+        }
+    }
+}
+
+pub struct Server {
+    listener: TcpListener,
+    limits: Limits,
+}
+
+impl Server {
+    pub fn bind(addr: impl ToSocketAddrs, limits: Limits) -> io::Result<Server> {
+        Ok(Server {
+            listener: TcpListener::bind(addr)?,
+            limits,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Answers connections forever, each on its own thread.
+    pub fn run(self) -> io::Result<()> {
+        for stream in self.listener.incoming() {
+            let stream = stream?;
+            let limits = self.limits;
+            thread::spawn(move || {
+                // The client going away is the only way this fails, and there is no one left to
+                // tell about it
+                let _ = handle(stream, limits);
+            });
+        }
+        Ok(())
+    }
+}
+
+fn handle(stream: TcpStream, limits: Limits) -> io::Result<()> {
+    let mut out = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match request(&line, limits) {
+            Ok(request) => respond(&mut out, request, limits)?,
+            Err(e) => writeln!(out, "{}", json!({ "error": e }))?,
+        }
+    }
+    Ok(())
+}
+
+/// What a client asked for.
+struct Request {
+    scramble: NissSequence<Move333>,
+    target: DrxsTarget,
+    builder: LinearSolverBuilder,
+}
+
+/// Reads a request, checking it against the limits.
+fn request(line: &str, limits: Limits) -> Result<Request, String> {
+    let v: Value = serde_json::from_str(line).map_err(|e| format!("invalid request: {e}"))?;
+    let usize_field = |name: &str| -> Result<Option<usize>, String> {
+        match &v[name] {
+            Value::Null => Ok(None),
+            n => n
+                .as_u64()
+                .map(|n| Some(n as usize))
+                .ok_or_else(|| format!("`{name}` should be a number")),
+        }
+    };
+    let bool_field = |name: &str| -> Result<bool, String> {
+        match &v[name] {
+            Value::Null => Ok(false),
+            b => b
+                .as_bool()
+                .ok_or_else(|| format!("`{name}` should be true or false")),
+        }
+    };
+
+    let scramble = v["scramble"]
+        .as_str()
+        .ok_or("the request has no `scramble`")?;
+    let scramble: NissSequence<Move333> = scramble
+        .parse()
+        .map_err(|e| format!("couldn't parse `{scramble}`: {e}"))?;
+
+    let max_depth = usize_field("max_depth")?.unwrap_or(limits.max_depth);
+    if max_depth > limits.max_depth {
+        return Err(format!(
+            "the maximum depth ({max_depth}) is above the server's limit ({})",
+            limits.max_depth
+        ));
+    }
+    let mut builder = LinearSolverBuilder::new()
+        .max_depth(max_depth)
+        .niss(bool_field("niss")?)
+        .variants(bool_field("all")?);
+    if let Some(d) = usize_field("min_depth")? {
+        builder = builder.min_depth(d);
+    }
+    if let Some(n) = usize_field("limit")? {
+        builder = builder.limit(n);
+    }
+    let target = DrxsTarget {
+        bad_slice: usize_field("bad_slice")?.unwrap_or(0),
+        ..DrxsTarget::default()
+    };
+    builder = builder.target(target.clone());
+    match &v["moves"] {
+        Value::Null => {}
+        Value::String(moves) => builder = builder.move_set(moves.parse::<MoveSet>()?),
+        _ => return Err("`moves` should be a string".to_string()),
+    }
+    Ok(Request {
+        scramble,
+        target,
+        builder,
+    })
+}
+
+/// Streams the solutions of a request to `out`. The search runs on its own thread so that it can
+/// be cancelled once the time limit is reached.
+fn respond(out: &mut impl Write, request: Request, limits: Limits) -> io::Result<()> {
+    let Request {
+        scramble,
+        target,
+        builder,
+    } = request;
+    let token = CancelToken::new();
+    let builder = builder.budget(Budget::new().cancel_token(token.clone()));
+    // Build before starting the thread so that a bad configuration can be reported
+    let sols = match builder.build(scramble.clone()) {
+        Ok(sols) => sols,
+        Err(e) => return writeln!(out, "{}", json!({ "error": e.to_string() })),
+    };
+
+    let (send, recv) = mpsc::channel();
+    let search = thread::spawn(move || {
+        for sol in sols {
+            // The receiver may have gone before the search notices it has been cancelled
+            if send.send(sol).is_err() {
+                break;
+            }
+        }
+    });
+
+    let deadline = Instant::now() + limits.time;
+    let mut count = 0;
+    let mut stream = || -> io::Result<bool> {
+        loop {
+            let sol = match recv.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(sol) => sol,
+                Err(RecvTimeoutError::Timeout) => return Ok(true),
+                Err(RecvTimeoutError::Disconnected) => return Ok(false),
+            };
+            count += 1;
+            let info = target.analyse(&finished_cube(&scramble, &sol));
+            let length = sol.normal.0.len() + sol.inverse.0.len();
+            writeln!(
+                out,
+                "{}",
+                json!({
+                    "solution": sol.to_string(),
+                    "length": length,
+                    "axis": info.map(|i| axis_name(i.axis)),
+                    "corners": info.map(|i| i.corners.to_string()),
+                    "bad_slice": info.map(|i| i.bad_slice),
+                    "flipped": info.map(|i| i.flipped),
+                })
+            )?;
+        }
+    };
+    let timed_out = stream();
+    // Whether the request timed out or the client went away, the search is stopped and waited for
+    // so that it can't keep using a core after its request is over
+    token.cancel();
+    let _ = search.join();
+    let timed_out = timed_out?;
+    writeln!(
+        out,
+        "{}",
+        json!({ "done": true, "count": count, "timed_out": timed_out })
+    )
+}
+
+#[cfg(test)]
+fn client(limits: Limits) -> (BufReader<TcpStream>, TcpStream) {
+    let server = Server::bind("127.0.0.1:0", limits).unwrap();
+    let addr = server.local_addr().unwrap();
+    thread::spawn(move || server.run());
+    let stream = TcpStream::connect(addr).unwrap();
+    (BufReader::new(stream.try_clone().unwrap()), stream)
+}
+
+#[cfg(test)]
+fn response(lines: &mut BufReader<TcpStream>) -> Vec<Value> {
+    let mut values = vec![];
+    loop {
+        let mut line = String::new();
+        lines.read_line(&mut line).unwrap();
+        let v: Value = serde_json::from_str(&line).unwrap();
+        let last = v["done"] == true || v["error"].is_string();
+        values.push(v);
+        if last {
+            return values;
+        }
+    }
+}
+
+#[test]
+fn solves_over_loopback() {
+    let (mut lines, mut stream) = client(Limits::default());
+
+    writeln!(stream, r#"{{"scramble": "R U F", "max_depth": 3}}"#).unwrap();
+    let values = response(&mut lines);
+    let expected: Vec<_> = LinearSolverBuilder::new()
+        .max_depth(3)
+        .build("R U F".parse().unwrap())
+        .unwrap()
+        .map(|sol| sol.to_string())
+        .collect();
+    let (done, sols) = values.split_last().unwrap();
+    assert_eq!(
+        sols.iter()
+            .map(|v| v["solution"].as_str().unwrap())
+            .collect::<Vec<_>>(),
+        expected
+    );
+    assert_eq!(done["count"], expected.len());
+    assert_eq!(done["timed_out"], false);
+
+    // The same connection can be used again, and bad requests are answered with an error
+    writeln!(
+        stream,
+        r#"{{"scramble": "R U F", "max_depth": 3, "limit": 2}}"#
+    )
+    .unwrap();
+    assert_eq!(response(&mut lines).len(), 3);
+    // "all" gives the variants the same way as the command line's -a
+    writeln!(
+        stream,
+        r#"{{"scramble": "R U F", "max_depth": 3, "niss": true, "all": true}}"#
+    )
+    .unwrap();
+    let expected: Vec<_> = LinearSolverBuilder::new()
+        .max_depth(3)
+        .niss(true)
+        .variants(true)
+        .build("R U F".parse().unwrap())
+        .unwrap()
+        .map(|sol| sol.to_string())
+        .collect();
+    let values = response(&mut lines);
+    assert_eq!(
+        values[..values.len() - 1]
+            .iter()
+            .map(|v| v["solution"].as_str().unwrap())
+            .collect::<Vec<_>>(),
+        expected
+    );
+    for request in [
+        "not json",
+        r#"{"max_depth": 3}"#,
+        r#"{"scramble": "R X"}"#,
+        r#"{"scramble": "R U F", "max_depth": 13}"#,
+        r#"{"scramble": "R U F", "min_depth": 4, "max_depth": 3}"#,
+    ] {
+        writeln!(stream, "{request}").unwrap();
+        let values = response(&mut lines);
+        assert_eq!(values.len(), 1);
+        assert!(values[0]["error"].is_string(), "{request}");
+    }
+}
+
+#[test]
+fn requests_time_out() {
+    let (mut lines, mut stream) = client(Limits {
+        max_depth: 20,
+        time: Duration::from_millis(50),
+    });
+    // There are far too many solutions up to 20 moves to find in time
+    writeln!(stream, r#"{{"scramble": "R U F", "niss": true}}"#).unwrap();
+    let start = Instant::now();
+    let done = response(&mut lines).pop().unwrap();
+    // The response only ends once the search has stopped, so this also checks that it was cancelled
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert_eq!(done["timed_out"], true);
+}
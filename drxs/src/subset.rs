@@ -0,0 +1,218 @@
+//! Names the half turn reduction subset of dr states, which tells FMC solvers how hard the step
+//! after dr is going to be.
+
+use std::fmt;
+
+use cube_lib::{
+    cube333::{
+        CubieCube,
+        axis::Axis,
+        corner::Corner,
+        edge::Edge,
+        moves::{Move333, Move333Type},
+    },
+    moves::MoveSequence,
+};
+
+use crate::{
+    CornerPattern, Symmetry, drxs_cases,
+    pipeline::is_dr,
+    prune::{coord_perm, half_turn_corners, htr_quarter_turns, perm_coord},
+    symmetry::{corner_faces, edge_faces},
+};
+
+/// The subset of a dr state. Its name is written the way FMC solvers write it, such as `4a1 4e`:
+/// the number of bad corners, a letter for how they lie, the number of quarter turns the corners
+/// need to reach a half turn reduction, and then the number of bad edges.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HtrSubset {
+    /// The axis of the dr.
+    pub axis: Axis,
+    /// The number of corners in the wrong tetrad, 0, 2 or 4. Swapping the two tetrads doesn't
+    /// make the corners any harder, so 6 and 8 count as 2 and 0.
+    pub bad_corners: usize,
+    /// Whether half turns can bring four bad corners onto the same layer of the axis, which is
+    /// the `a` case. Otherwise four bad corners are the `b` case, and fewer bad corners are always
+    /// written with a `c`.
+    pub one_layer: bool,
+    /// The fewest quarter turns of the axis' faces which take the corners to a half turn
+    /// reduction, with any number of half turns between them.
+    pub quarter_turns: usize,
+    /// The number of edges, outside of the axis' slice, which are in the wrong slice of the other
+    /// two axes.
+    pub bad_edges: usize,
+    /// The number of the axis' slice edges which are outside of the slice, which is 0 for a dr.
+    /// These edges, and the edges in their places, aren't counted in `bad_edges`.
+    pub bad_slice: usize,
+}
+
+impl HtrSubset {
+    /// The name of the corner case, such as `4a1`.
+    pub fn corner_case(&self) -> String {
+        let letter = match (self.bad_corners, self.one_layer) {
+            (4, true) => 'a',
+            (4, false) => 'b',
+            _ => 'c',
+        };
+        format!("{}{letter}{}", self.bad_corners, self.quarter_turns)
+    }
+}
+
+impl fmt::Display for HtrSubset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}e", self.corner_case(), self.bad_edges)?;
+        if self.bad_slice > 0 {
+            write!(f, ", {} bad slice", self.bad_slice)?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether corner position `i` is in the tetrad of UFR, rather than the tetrad of UFL. Half turns
+/// never take a corner out of its tetrad.
+fn tetrad(i: usize) -> bool {
+    use Move333Type as T;
+
+    let faces = corner_faces(Corner::ALL[i]);
+    faces
+        .iter()
+        .filter(|f| [T::D, T::B, T::L].contains(f))
+        .count()
+        % 2
+        == 0
+}
+
+/// The positions of the corners which are in the wrong tetrad, for a permutation as in
+/// `prune::perm_coord`.
+fn bad_corners(perm: &[usize; 8]) -> Vec<usize> {
+    (0..8).filter(|&i| tetrad(i) != tetrad(perm[i])).collect()
+}
+
+/// Whether the positions are all on the U layer or all on the D layer.
+fn on_one_layer(positions: &[usize]) -> bool {
+    let in_u = positions
+        .iter()
+        .filter(|&&i| corner_faces(Corner::ALL[i]).contains(&Move333Type::U))
+        .count();
+    in_u == 0 || in_u == positions.len()
+}
+
+/// The subset of the cube, if it is in dr on some axis.
+pub fn htr_subset(c: &CubieCube) -> Option<HtrSubset> {
+    Axis::AXES.into_iter().find_map(|a| axis_subset(c, a))
+}
+
+/// The subset that a solution from `cube` finishes in, if it finishes in dr.
+pub fn solution_subset(cube: &CubieCube, sol: &MoveSequence<Move333>) -> Option<HtrSubset> {
+    let finished = sol.0.iter().fold(cube.clone(), |c, &m| c.make_move(m));
+    htr_subset(&finished)
+}
+
+/// The subset of the cube, if it is in dr on axis `a`, see `htr_subset`.
+pub fn axis_subset(c: &CubieCube, a: Axis) -> Option<HtrSubset> {
+    is_dr(c, a).then(|| subset(c, a, 0))
+}
+
+/// The subset of a dr-xs with oriented corners, which is the subset of the dr it leads to when
+/// the slice edges are put into the slice, such as `4a1 2e, 2 bad slice`. The corners are already
+/// as they would be in that dr, but only the edges which are placed like a dr's edges can be
+/// counted. A dr gets the same subset as from `htr_subset`. If the cube is in a dr-xs on more
+/// than one axis, the one with the fewest slice edges out of the slice is used.
+pub fn drxs_subset(c: &CubieCube) -> Option<HtrSubset> {
+    let info = (Axis::AXES.into_iter())
+        .flat_map(|a| drxs_cases(c, a))
+        .filter(|info| info.corners == CornerPattern::Oriented)
+        .min_by_key(|info| info.bad_slice)?;
+    Some(subset(c, info.axis, info.bad_slice))
+}
+
+/// The subset on axis `a` of a cube with oriented corners and `bad_slice` slice edges outside of
+/// the slice.
+fn subset(c: &CubieCube, a: Axis, bad_slice: usize) -> HtrSubset {
+    // Look at the cube from the side which turns the axis into UD
+    let s = Symmetry::to_ud(a);
+    let mut perm = [0; 8];
+    for i in 0..8 {
+        let piece = Corner::ALL.iter().position(|&p| p == c.cp[i]).unwrap();
+        perm[s.corner(i)] = s.corner(piece);
+    }
+    let mut ep = [Edge::UF; 12];
+    for k in 0..12 {
+        let piece = Edge::ALL.iter().position(|&e| e == c.ep[k]).unwrap();
+        ep[s.edge(k)] = Edge::ALL[s.edge(piece)];
+    }
+
+    let bad = bad_corners(&perm).len();
+    // The edges of the U and D layers have to be in the right one of the M and S slices. A slice
+    // edge there has no right slice until it is put into the E slice
+    let bad_edges = (0..12)
+        .filter(|&k| {
+            edge_faces(Edge::ALL[k])
+                .iter()
+                .any(|&f| matches!(f, Move333Type::U | Move333Type::D))
+        })
+        .filter(|&k| !ep[k].e_slice() && Edge::ALL[k].m_slice() != ep[k].m_slice())
+        .count();
+
+    HtrSubset {
+        axis: a,
+        bad_corners: bad.min(8 - bad),
+        // Every state the half turns reach has to be looked at
+        one_layer: bad == 4
+            && (half_turn_corners().iter().enumerate())
+                .filter(|&(_, &h)| h)
+                .any(|(h, _)| on_one_layer(&bad_corners(&coord_perm(h).map(|from| perm[from])))),
+        quarter_turns: htr_quarter_turns()[perm_coord(perm)] as usize,
+        bad_edges,
+        bad_slice,
+    }
+}
+
+#[test]
+fn subsets() {
+    let subset = |scramble: &str| {
+        let cube = CubieCube::SOLVED.make_niss_moves(scramble.parse().unwrap());
+        htr_subset(&cube).map(|s| s.to_string())
+    };
+
+    // Moving the corners around with half turns keeps them in a half turn reduction
+    assert_eq!(subset(""), Some("0c0 0e".to_string()));
+    assert_eq!(subset("R2 U2 F2 D2 L2"), Some("0c0 0e".to_string()));
+    // A single quarter turn of the axis is the easiest case with bad corners
+    assert_eq!(subset("U"), Some("4a1 4e".to_string()));
+    assert_eq!(subset("R2 D' F2"), Some("4a1 4e".to_string()));
+    // Every corner in the wrong tetrad counts as none
+    assert_eq!(subset("U D"), Some("0c2 8e".to_string()));
+    assert_eq!(subset("U F2 U2 R2 U"), Some("4a2 4e".to_string()));
+    assert_eq!(subset("U R2 U"), Some("4b2 2e".to_string()));
+    assert_eq!(subset("U2 R2 U"), Some("4a1 4e".to_string()));
+    // The same cases on the other axes
+    assert_eq!(subset("R"), Some("4a1 4e".to_string()));
+    assert_eq!(subset("F' U2 B2"), Some("4a1 4e".to_string()));
+    // Not dr
+    assert_eq!(subset("R U"), None);
+
+    let drxs = |scramble: &str| {
+        let cube = CubieCube::SOLVED.make_niss_moves(scramble.parse().unwrap());
+        drxs_subset(&cube).map(|s| s.to_string())
+    };
+    assert_eq!(drxs("U F2 U2 R2 U"), subset("U F2 U2 R2 U"));
+    // The two slice edges out of the slice, and the edges in their places, aren't counted
+    assert_eq!(drxs("R U F2 B2 D"), Some("4a1 3e, 2 bad slice".to_string()));
+    assert_eq!(subset("R U F2 B2 D"), None);
+    // A dr-xs with twisted corners has no subset yet
+    assert_eq!(drxs("R U F B' R'"), None);
+
+    let moves: MoveSequence<Move333> = "R2 U F2 D' L2 U2 B2 D".parse().unwrap();
+    let cube = moves
+        .0
+        .iter()
+        .fold(CubieCube::SOLVED, |c, &m| c.make_move(m));
+    assert_eq!(
+        solution_subset(&CubieCube::SOLVED, &moves),
+        htr_subset(&cube)
+    );
+    let subset = htr_subset(&cube).unwrap();
+    assert_eq!(subset.axis, Axis::UD);
+    assert!(subset.quarter_turns <= 3);
+}
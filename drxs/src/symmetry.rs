@@ -0,0 +1,290 @@
+//! The 48 symmetries of the cube, as maps of the faces.
+//!
+//! The dr-xs checks are only written down for the UD axis, in `COS` and `IDXS`. The checks for the
+//! other axes use the rotation which takes that axis to UD to find where their slice edges have to
+//! be.
+
+use std::{collections::HashSet, sync::OnceLock};
+
+use cube_lib::{
+    cube333::{
+        CubieCube,
+        axis::Axis,
+        corner::Corner,
+        edge::Edge,
+        moves::{Move333, Move333Type},
+    },
+    moves::{MoveSequence, NissSequence},
+};
+
+use crate::{IDXS, prune::move_index, sort_axes};
+
+/// The faces, in the order of the search, which is also the order `Symmetry` stores them in.
+const FACES: [Move333Type; 6] = {
+    use Move333Type as T;
+    [T::R, T::L, T::U, T::D, T::F, T::B]
+};
+
+/// The position of a face in `FACES`. Faces `2i` and `2i + 1` are opposite each other, and point
+/// along the positive and negative direction of axis `i` of a right handed coordinate system.
+fn face_index(ty: Move333Type) -> usize {
+    FACES.iter().position(|&f| f == ty).unwrap()
+}
+
+pub(crate) fn corner_faces(c: Corner) -> [Move333Type; 3] {
+    use Move333Type as T;
+
+    match c {
+        Corner::UFR => [T::U, T::F, T::R],
+        Corner::UFL => [T::U, T::F, T::L],
+        Corner::UBL => [T::U, T::B, T::L],
+        Corner::UBR => [T::U, T::B, T::R],
+        Corner::DFR => [T::D, T::F, T::R],
+        Corner::DFL => [T::D, T::F, T::L],
+        Corner::DBL => [T::D, T::B, T::L],
+        Corner::DBR => [T::D, T::B, T::R],
+    }
+}
+
+pub(crate) fn edge_faces(e: Edge) -> [Move333Type; 2] {
+    use Move333Type as T;
+
+    match e {
+        Edge::UF => [T::U, T::F],
+        Edge::UL => [T::U, T::L],
+        Edge::UB => [T::U, T::B],
+        Edge::UR => [T::U, T::R],
+        Edge::DF => [T::D, T::F],
+        Edge::DL => [T::D, T::L],
+        Edge::DB => [T::D, T::B],
+        Edge::DR => [T::D, T::R],
+        Edge::FR => [T::F, T::R],
+        Edge::FL => [T::F, T::L],
+        Edge::BL => [T::B, T::L],
+        Edge::BR => [T::B, T::R],
+    }
+}
+
+/// The position, in the order of `ALL`, of the piece touching exactly the faces in `faces`.
+fn position<P: Copy, const N: usize>(
+    all: &[P],
+    piece_faces: impl Fn(P) -> [Move333Type; N],
+    faces: [Move333Type; N],
+) -> usize {
+    all.iter()
+        .position(|&p| piece_faces(p).iter().all(|f| faces.contains(f)))
+        .unwrap()
+}
+
+/// A symmetry of the cube: a rotation, possibly followed by a reflection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Symmetry {
+    /// The face that each face of `FACES` is taken to.
+    faces: [Move333Type; 6],
+    /// Whether this reverses the handedness of the cube, so that it turns clockwise moves into
+    /// anticlockwise ones.
+    mirror: bool,
+}
+
+impl Symmetry {
+    /// Every symmetry, starting with the identity.
+    pub fn all() -> impl Iterator<Item = Symmetry> {
+        // Each symmetry permutes the three axes and then may reverse any of them
+        const PERMS: [[usize; 3]; 6] = [
+            [0, 1, 2],
+            [0, 2, 1],
+            [1, 0, 2],
+            [1, 2, 0],
+            [2, 0, 1],
+            [2, 1, 0],
+        ];
+        PERMS.into_iter().flat_map(|perm| {
+            let odd = [perm[1] < perm[0], perm[2] < perm[0], perm[2] < perm[1]]
+                .into_iter()
+                .filter(|&b| b)
+                .count()
+                % 2
+                == 1;
+            (0..8).map(move |reversed: usize| Symmetry {
+                faces: std::array::from_fn(|i| {
+                    let (axis, negative) = (i / 2, i % 2 == 1);
+                    let negative = negative != (reversed >> axis & 1 == 1);
+                    FACES[2 * perm[axis] + negative as usize]
+                }),
+                mirror: odd != (reversed.count_ones() % 2 == 1),
+            })
+        })
+    }
+
+    /// The rotation about the UFR and DBL corners which takes axis `a` to UD, so it is the
+    /// identity for UD and cycles the axes for FB and LR.
+    pub fn to_ud(a: Axis) -> Symmetry {
+        use Move333Type as T;
+
+        let faces = match a {
+            Axis::UD => FACES,
+            // F → U → R → F
+            Axis::FB => [T::F, T::B, T::R, T::L, T::U, T::D],
+            // R → U → F → R
+            Axis::LR => [T::U, T::D, T::F, T::B, T::R, T::L],
+        };
+        Symmetry {
+            faces,
+            mirror: false,
+        }
+    }
+
+    pub fn is_mirror(&self) -> bool {
+        self.mirror
+    }
+
+    pub fn face(&self, ty: Move333Type) -> Move333Type {
+        self.faces[face_index(ty)]
+    }
+
+    pub fn axis(&self, a: Axis) -> Axis {
+        let ty = match a {
+            Axis::UD => Move333Type::U,
+            Axis::FB => Move333Type::F,
+            Axis::LR => Move333Type::R,
+        };
+        match self.face(ty) {
+            Move333Type::U | Move333Type::D => Axis::UD,
+            Move333Type::F | Move333Type::B => Axis::FB,
+            Move333Type::R | Move333Type::L => Axis::LR,
+        }
+    }
+
+    /// Where the corner position `i` (in the order of `Corner::ALL`) is taken to.
+    pub fn corner(&self, i: usize) -> usize {
+        let faces = corner_faces(Corner::ALL[i]).map(|f| self.face(f));
+        position(&Corner::ALL, corner_faces, faces)
+    }
+
+    /// Where the edge position `k` (in the order of `Edge::ALL`) is taken to.
+    pub fn edge(&self, k: usize) -> usize {
+        let faces = edge_faces(Edge::ALL[k]).map(|f| self.face(f));
+        position(&Edge::ALL, edge_faces, faces)
+    }
+
+    /// The move which does to the image of a cube what `m` does to the cube.
+    pub fn apply(&self, m: Move333) -> Move333 {
+        Move333 {
+            ty: self.face(m.ty),
+            count: if self.mirror { 4 - m.count } else { m.count },
+        }
+    }
+
+    pub fn apply_sequence(&self, moves: &MoveSequence<Move333>) -> MoveSequence<Move333> {
+        MoveSequence(moves.0.iter().map(|&m| self.apply(m)).collect())
+    }
+
+    pub fn apply_niss(&self, moves: &NissSequence<Move333>) -> NissSequence<Move333> {
+        NissSequence {
+            normal: self.apply_sequence(&moves.normal),
+            inverse: self.apply_sequence(&moves.inverse),
+        }
+    }
+
+    /// The symmetries which leave the cube made by `scramble` as it is. Their images of a solution
+    /// are solutions too.
+    pub fn fixing(scramble: &NissSequence<Move333>) -> Vec<Symmetry> {
+        let cube = CubieCube::SOLVED.make_niss_moves(scramble.clone());
+        Symmetry::all()
+            .filter(|s| CubieCube::SOLVED.make_niss_moves(s.apply_niss(scramble)) == cube)
+            .collect()
+    }
+}
+
+/// The edge positions that the slice edges of axis `a` have to be in when the corners have the
+/// orientation `COS[j]`. These are the positions which `Symmetry::to_ud(a)` takes to `IDXS[j]`.
+pub(crate) fn axis_slice(a: Axis, j: usize) -> [usize; 4] {
+    static SLICES: OnceLock<[[[usize; 4]; 3]; 3]> = OnceLock::new();
+    let slices = SLICES.get_or_init(|| {
+        [Axis::UD, Axis::FB, Axis::LR].map(|a| {
+            let s = Symmetry::to_ud(a);
+            IDXS.map(|idxs| {
+                let mut ks = (0..12).filter(|&k| idxs.contains(&s.edge(k)));
+                std::array::from_fn(|_| ks.next().unwrap())
+            })
+        })
+    });
+    let a = match a {
+        Axis::UD => 0,
+        Axis::FB => 1,
+        Axis::LR => 2,
+    };
+    slices[a][j]
+}
+
+/// Collapses solutions which are images of each other under the symmetries of a scramble.
+pub(crate) struct SymmetryFilter {
+    symmetries: Vec<Symmetry>,
+    /// The images of every solution let through so far, see `SymmetryFilter::key`.
+    seen: HashSet<(Vec<u8>, Vec<u8>)>,
+}
+
+impl SymmetryFilter {
+    pub(crate) fn new(scramble: &NissSequence<Move333>) -> SymmetryFilter {
+        SymmetryFilter {
+            symmetries: Symmetry::fixing(scramble),
+            seen: HashSet::new(),
+        }
+    }
+
+    /// A solution as the indices of its moves on each side, which can be hashed. The moves are
+    /// put in the search order first, so that an image is found whichever way round its
+    /// commuting moves came out.
+    fn key(sol: &NissSequence<Move333>) -> (Vec<u8>, Vec<u8>) {
+        let side = |moves: &MoveSequence<Move333>| {
+            let mut moves = moves.0.clone();
+            sort_axes(&mut moves);
+            moves.into_iter().map(|m| move_index(m) as u8).collect()
+        };
+        (side(&sol.normal), side(&sol.inverse))
+    }
+
+    /// Whether `sol` isn't the image of a solution let through before.
+    pub(crate) fn is_new(&mut self, sol: &NissSequence<Move333>) -> bool {
+        if self.seen.contains(&Self::key(sol)) {
+            return false;
+        }
+        for s in &self.symmetries {
+            self.seen.insert(Self::key(&s.apply_niss(sol)));
+        }
+        true
+    }
+}
+
+#[test]
+fn symmetries() {
+    let all: Vec<_> = Symmetry::all().collect();
+    assert_eq!(all.len(), 48);
+    assert_eq!(all[0].faces, FACES);
+    assert!(all.iter().enumerate().all(|(i, s)| !all[..i].contains(s)));
+    assert_eq!(all.iter().filter(|s| s.is_mirror()).count(), 24);
+
+    let scramble: NissSequence<Move333> = "R U2 F' (D L2)".parse().unwrap();
+    let cube = CubieCube::SOLVED.make_niss_moves(scramble.clone());
+    for s in &all {
+        // The scramble isn't symmetric, so only the identity keeps its cube
+        let image = CubieCube::SOLVED.make_niss_moves(s.apply_niss(&scramble));
+        assert_eq!(image == cube, s.faces == FACES);
+        let corners: Vec<_> = (0..8).map(|i| s.corner(i)).collect();
+        assert!((0..8).all(|i| corners.contains(&i)));
+        let edges: Vec<_> = (0..12).map(|k| s.edge(k)).collect();
+        assert!((0..12).all(|k| edges.contains(&k)));
+    }
+
+    // The hand written slices of the other axes
+    for (a, slices) in [
+        (Axis::UD, [[8, 9, 10, 11], [0, 2, 4, 6], [1, 3, 5, 7]]),
+        (Axis::FB, [[1, 3, 5, 7], [8, 9, 10, 11], [0, 2, 4, 6]]),
+        (Axis::LR, [[0, 2, 4, 6], [1, 3, 5, 7], [8, 9, 10, 11]]),
+    ] {
+        assert_eq!(Symmetry::to_ud(a).axis(a), Axis::UD);
+        for (j, idxs) in slices.into_iter().enumerate() {
+            assert_eq!(axis_slice(a, j), idxs);
+        }
+    }
+}
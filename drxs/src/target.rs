@@ -0,0 +1,373 @@
+use std::{fmt, str::FromStr};
+
+use cube_lib::cube333::{CubieCube, axis::Axis, corner::CornerTwist, edge::EdgeFlip};
+
+use crate::{COS, is_slice_edge, symmetry::axis_slice};
+
+/// How the slice edges that are in their slice have to be oriented.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SliceFlip {
+    /// Every slice edge in the slice is oriented.
+    Oriented,
+    /// Every slice edge in the slice is flipped.
+    Flipped,
+    /// The slice edges in the slice are either all oriented or all flipped.
+    Either,
+}
+
+/// The corner orientations (relative to the axis of the slice edges) that a dr-xs can have.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CornerPattern {
+    /// Every corner is oriented.
+    Oriented = 0,
+    /// The corners are alternately twisted clockwise and anticlockwise, starting with clockwise.
+    Clockwise = 1,
+    /// The corners are alternately twisted anticlockwise and clockwise, starting with
+    /// anticlockwise.
+    AntiClockwise = 2,
+}
+
+impl CornerPattern {
+    pub const ALL: [CornerPattern; 3] = [
+        CornerPattern::Oriented,
+        CornerPattern::Clockwise,
+        CornerPattern::AntiClockwise,
+    ];
+}
+
+/// Which dr-xs a cube is in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DrxsInfo {
+    /// The axis whose slice edges are solved.
+    pub axis: Axis,
+    pub corners: CornerPattern,
+    /// The number of slice edges outside of the slice.
+    pub bad_slice: usize,
+    /// The number of edges (of any kind) which are flipped relative to the axis.
+    pub flipped: usize,
+}
+
+/// How far a cube is from being in a dr-xs on one axis with oriented corners.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AxisStatus {
+    pub axis: Axis,
+    /// The number of edges which are flipped relative to the axis.
+    pub flipped: usize,
+    /// The number of corners which are twisted relative to the axis.
+    pub twisted: usize,
+    /// The number of the axis' slice edges which are in their slice.
+    pub slice: usize,
+}
+
+impl AxisStatus {
+    pub fn new(c: &CubieCube, a: Axis) -> AxisStatus {
+        AxisStatus {
+            axis: a,
+            flipped: c
+                .axis_eo(a)
+                .into_iter()
+                .filter(|&f| f == EdgeFlip::Flipped)
+                .count(),
+            twisted: c
+                .axis_co(a)
+                .into_iter()
+                .filter(|&t| t != CornerTwist::Oriented)
+                .count(),
+            slice: axis_slice(a, 0)
+                .into_iter()
+                .filter(|&k| is_slice_edge(c, a, k))
+                .count(),
+        }
+    }
+}
+
+/// Every dr-xs on axis `a` that a cube is in, whatever number of slice edges are out of the
+/// slice. There is at most one for each corner pattern.
+pub fn drxs_cases(c: &CubieCube, a: Axis) -> Vec<DrxsInfo> {
+    CornerPattern::ALL
+        .into_iter()
+        .filter_map(|p| {
+            (0..=4).find_map(|bad_slice| {
+                DrxsTarget {
+                    axes: vec![a],
+                    bad_slice,
+                    flip: SliceFlip::Either,
+                    corners: vec![p],
+                }
+                .analyse(c)
+            })
+        })
+        .collect()
+}
+
+/// The kinds of dr-xs that a solver should look for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DrxsTarget {
+    /// The axes whose slice edges we are allowed to solve.
+    pub axes: Vec<Axis>,
+    /// The number of slice edges which must be outside of the slice.
+    pub bad_slice: usize,
+    /// How the slice edges in the slice must be oriented.
+    pub flip: SliceFlip,
+    /// The corner orientations we are allowed to end with.
+    pub corners: Vec<CornerPattern>,
+}
+
+impl Default for DrxsTarget {
+    /// Any dr-xs with every slice edge in the slice.
+    fn default() -> Self {
+        DrxsTarget {
+            axes: Axis::AXES.to_vec(),
+            bad_slice: 0,
+            flip: SliceFlip::Either,
+            corners: CornerPattern::ALL.to_vec(),
+        }
+    }
+}
+
+impl DrxsTarget {
+    /// Determines whether the slice edges are in a state allowed by this target, given which
+    /// positions hold slice edges, which positions are flipped and which positions make up the
+    /// slice.
+    pub(crate) fn slice_matches(
+        &self,
+        slice: [bool; 12],
+        flips: [bool; 12],
+        idxs: [usize; 4],
+    ) -> bool {
+        let placed = idxs.into_iter().filter(|&k| slice[k]);
+        let flipped = placed.clone().filter(|&k| flips[k]).count();
+        let placed = placed.count();
+        placed + self.bad_slice == 4
+            && match self.flip {
+                SliceFlip::Oriented => flipped == 0,
+                SliceFlip::Flipped => flipped == placed,
+                SliceFlip::Either => flipped == 0 || flipped == placed,
+            }
+    }
+
+    /// Determines whether a CubieCube is in one of the dr-xs states described by this target.
+    pub fn is_solved(&self, c: &CubieCube) -> bool {
+        self.analyse(c).is_some()
+    }
+
+    /// Finds which of the dr-xs states described by this target a CubieCube is in. If it is in
+    /// more than one, the first axis and corner pattern of the target are preferred.
+    pub fn analyse(&self, c: &CubieCube) -> Option<DrxsInfo> {
+        self.axes.iter().find_map(|&a| {
+            let co = c.axis_co(a);
+            let eo = c.axis_eo(a);
+            let slice: [bool; 12] = std::array::from_fn(|k| is_slice_edge(c, a, k));
+            let flips = eo.map(|f| f == EdgeFlip::Flipped);
+            self.corners.iter().find_map(|&p| {
+                let j = p as usize;
+                let idxs = axis_slice(a, j);
+                (COS[j] == co && self.slice_matches(slice, flips, idxs)).then(|| DrxsInfo {
+                    axis: a,
+                    corners: p,
+                    bad_slice: idxs.into_iter().filter(|&k| !slice[k]).count(),
+                    flipped: flips.into_iter().filter(|&f| f).count(),
+                })
+            })
+        })
+    }
+}
+
+/// The name of an axis as it is parsed by `parse_axis`.
+pub fn axis_name(a: Axis) -> &'static str {
+    match a {
+        Axis::UD => "ud",
+        Axis::FB => "fb",
+        Axis::LR => "lr",
+    }
+}
+
+/// Parses an axis written as `ud`, `fb` or `lr`.
+pub fn parse_axis(s: &str) -> Result<Axis, String> {
+    match s.to_ascii_lowercase().as_str() {
+        "ud" => Ok(Axis::UD),
+        "fb" => Ok(Axis::FB),
+        "lr" => Ok(Axis::LR),
+        _ => Err(format!("unknown axis `{s}`, expected one of ud, fb or lr")),
+    }
+}
+
+impl fmt::Display for SliceFlip {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SliceFlip::Oriented => "oriented",
+            SliceFlip::Flipped => "flipped",
+            SliceFlip::Either => "either",
+        })
+    }
+}
+
+impl fmt::Display for CornerPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CornerPattern::Oriented => "oriented",
+            CornerPattern::Clockwise => "clockwise",
+            CornerPattern::AntiClockwise => "anticlockwise",
+        })
+    }
+}
+
+impl FromStr for SliceFlip {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "oriented" => Ok(SliceFlip::Oriented),
+            "flipped" => Ok(SliceFlip::Flipped),
+            "either" => Ok(SliceFlip::Either),
+            _ => Err(format!(
+                "unknown slice flip `{s}`, expected one of oriented, flipped or either"
+            )),
+        }
+    }
+}
+
+impl FromStr for CornerPattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "oriented" => Ok(CornerPattern::Oriented),
+            "clockwise" | "cw" => Ok(CornerPattern::Clockwise),
+            "anticlockwise" | "acw" => Ok(CornerPattern::AntiClockwise),
+            _ => Err(format!(
+                "unknown corner pattern `{s}`, expected one of oriented, clockwise or anticlockwise"
+            )),
+        }
+    }
+}
+
+#[test]
+fn slice_matching() {
+    let idxs = crate::IDXS[0];
+    let mut slice = [false; 12];
+    let mut flips = [false; 12];
+    for k in idxs {
+        slice[k] = true;
+    }
+    let target = |bad_slice, flip| DrxsTarget {
+        bad_slice,
+        flip,
+        ..DrxsTarget::default()
+    };
+
+    assert!(target(0, SliceFlip::Oriented).slice_matches(slice, flips, idxs));
+    assert!(target(0, SliceFlip::Either).slice_matches(slice, flips, idxs));
+    assert!(!target(0, SliceFlip::Flipped).slice_matches(slice, flips, idxs));
+    assert!(!target(1, SliceFlip::Either).slice_matches(slice, flips, idxs));
+
+    // Move two slice edges out of the slice and flip the other two
+    slice[idxs[0]] = false;
+    slice[idxs[1]] = false;
+    slice[0] = true;
+    slice[1] = true;
+    flips[idxs[2]] = true;
+    flips[idxs[3]] = true;
+    assert!(target(2, SliceFlip::Flipped).slice_matches(slice, flips, idxs));
+    assert!(target(2, SliceFlip::Either).slice_matches(slice, flips, idxs));
+    assert!(!target(2, SliceFlip::Oriented).slice_matches(slice, flips, idxs));
+    assert!(!target(0, SliceFlip::Either).slice_matches(slice, flips, idxs));
+
+    // Only one of the slice edges in the slice being flipped is never allowed
+    flips[idxs[2]] = false;
+    assert!(!target(2, SliceFlip::Either).slice_matches(slice, flips, idxs));
+}
+
+#[test]
+fn axis_targets() {
+    use cube_lib::mv;
+
+    // R keeps the M slice and the corner orientation relative to R and L
+    let cube = CubieCube::SOLVED.make_move(mv!(R, 1));
+    let on = |axes: &[Axis]| DrxsTarget {
+        axes: axes.to_vec(),
+        ..DrxsTarget::default()
+    };
+    assert!(DrxsTarget::default().is_solved(&cube));
+    assert!(on(&[Axis::LR]).is_solved(&cube));
+    assert!(on(&[Axis::UD, Axis::LR]).is_solved(&cube));
+    assert!(!on(&[Axis::UD, Axis::FB]).is_solved(&cube));
+    assert!(!on(&[]).is_solved(&cube));
+
+    let corners = DrxsTarget {
+        corners: vec![CornerPattern::Clockwise, CornerPattern::AntiClockwise],
+        ..DrxsTarget::default()
+    };
+    assert!(!corners.is_solved(&cube));
+}
+
+#[test]
+fn analysis() {
+    use cube_lib::mv;
+
+    let info = DrxsTarget::default()
+        .analyse(&CubieCube::SOLVED.make_move(mv!(R, 1)))
+        .unwrap();
+    assert_eq!(info.axis, Axis::LR);
+    assert_eq!(info.corners, CornerPattern::Oriented);
+    assert_eq!(info.bad_slice, 0);
+
+    // A dr-xs with one of the M slice edges left out of the slice
+    let bad = DrxsTarget {
+        axes: vec![Axis::LR],
+        bad_slice: 1,
+        ..DrxsTarget::default()
+    };
+    let cube = CubieCube::SOLVED.make_niss_moves("R U F".parse().unwrap());
+    let sol = crate::LinearSolverBuilder::new()
+        .target(bad.clone())
+        .build("R U F".parse().unwrap())
+        .unwrap()
+        .next()
+        .unwrap();
+    let cube = sol.normal.0.iter().fold(cube, |c, &m| c.make_move(m));
+    let info = bad.analyse(&cube).unwrap();
+    assert_eq!((info.axis, info.bad_slice), (Axis::LR, 1));
+    assert!(
+        DrxsTarget::default()
+            .analyse(&cube)
+            .is_none_or(|i| i.axis != Axis::LR)
+    );
+}
+
+#[test]
+fn axis_status() {
+    use cube_lib::mv;
+
+    // R only flips edges relative to R and L
+    let cube = CubieCube::SOLVED.make_move(mv!(R, 1));
+    let lr = AxisStatus::new(&cube, Axis::LR);
+    assert_eq!((lr.twisted, lr.slice), (0, 4));
+    assert_eq!(
+        AxisStatus::new(&cube, Axis::UD),
+        AxisStatus {
+            axis: Axis::UD,
+            flipped: 0,
+            twisted: 4,
+            slice: 2
+        }
+    );
+}
+
+#[test]
+fn cases() {
+    use cube_lib::mv;
+
+    let cube = CubieCube::SOLVED.make_move(mv!(R, 1));
+    let cases = drxs_cases(&cube, Axis::LR);
+    assert_eq!(cases.len(), 1);
+    assert_eq!(
+        (cases[0].axis, cases[0].corners, cases[0].bad_slice),
+        (Axis::LR, CornerPattern::Oriented, 0)
+    );
+    assert!(drxs_cases(&cube, Axis::UD).is_empty());
+    // The solved cube is in dr on every axis
+    for a in Axis::AXES {
+        assert_eq!(drxs_cases(&CubieCube::SOLVED, a).len(), 1);
+    }
+}
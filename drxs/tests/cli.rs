@@ -0,0 +1,508 @@
+use std::{
+    io::Write,
+    path::Path,
+    process::{Command, Output, Stdio},
+};
+
+const SCRAMBLE: &str = "R U F";
+
+fn drxs(args: &[&str]) -> Vec<String> {
+    run(&[args, &[SCRAMBLE]].concat())
+}
+
+fn run(args: &[&str]) -> Vec<String> {
+    let out = output(args);
+    assert!(out.status.success());
+    String::from_utf8(out.stdout)
+        .unwrap()
+        .lines()
+        .map(|l| l.to_string())
+        .collect()
+}
+
+/// Runs drxs, keeping its tables in the test's temporary directory.
+fn output(args: &[&str]) -> Output {
+    output_in(Path::new(env!("CARGO_TARGET_TMPDIR")), args)
+}
+
+/// Runs drxs with its tables kept in `cache`.
+fn output_in(cache: &Path, args: &[&str]) -> Output {
+    command(cache).args(args).output().unwrap()
+}
+
+fn command(cache: &Path) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_drxs"));
+    command.env("CUBE_TABLE_CACHE", cache);
+    command
+}
+
+/// Splits a line of the form `moves (n)` into its moves and move count.
+fn split_count(line: &str) -> (&str, usize) {
+    let (sol, n) = line.rsplit_once(" (").unwrap();
+    (sol, n.strip_suffix(')').unwrap().parse().unwrap())
+}
+
+fn move_count(sol: &str) -> usize {
+    sol.split_whitespace().count()
+}
+
+#[test]
+fn prints_move_counts() {
+    let lines = drxs(&["-M", "3"]);
+    assert!(!lines.is_empty());
+    for line in lines {
+        let (sol, n) = split_count(&line);
+        assert_eq!(move_count(sol), n);
+    }
+}
+
+#[test]
+fn plain() {
+    let lines = drxs(&["-p", "-M", "3"]);
+    let counted = drxs(&["-M", "3"]);
+    assert_eq!(lines.len(), counted.len());
+    for (line, counted) in lines.iter().zip(counted) {
+        assert_eq!(line, split_count(&counted).0);
+    }
+}
+
+#[test]
+fn count() {
+    let lines = drxs(&["-M", "3"]);
+    assert_eq!(drxs(&["-c", "-M", "3"]), vec![lines.len().to_string()]);
+}
+
+#[test]
+fn min_max() {
+    let lines = drxs(&["-p", "-m", "3", "-M", "3"]);
+    assert!(!lines.is_empty());
+    assert!(lines.iter().all(|l| move_count(l) == 3));
+
+    let lines = drxs(&["-p", "-M", "2"]);
+    assert!(!lines.is_empty());
+    assert!(lines.iter().all(|l| move_count(l) <= 2));
+}
+
+#[test]
+fn num() {
+    assert_eq!(drxs(&["-n", "2"]).len(), 2);
+    assert_eq!(drxs(&["-c", "-n", "5"]), vec!["5"]);
+}
+
+#[test]
+fn optimal() {
+    let lines = drxs(&["-p", "-o"]);
+    let opt = move_count(&drxs(&["-p", "-n", "1"])[0]);
+    assert!(!lines.is_empty());
+    assert!(lines.iter().all(|l| move_count(l) == opt));
+    assert_eq!(lines, drxs(&["-p", "-m", "0", "-M", &opt.to_string()]));
+
+    let lines = drxs(&["-p", "-O", "1"]);
+    assert!(lines.iter().all(|l| move_count(l) <= opt + 1));
+    assert!(lines.iter().any(|l| move_count(l) == opt + 1));
+}
+
+#[test]
+fn niss() {
+    let normal = drxs(&["-p", "-M", "2"]);
+    let niss = drxs(&["-p", "-N", "-M", "2"]);
+    // Solutions only on the inverse, and solutions switching part way through
+    assert!(niss.iter().any(|l| l.starts_with('(')));
+    assert!(niss.iter().any(|l| !l.starts_with('(') && l.contains('(')));
+    assert_eq!(
+        niss.iter().filter(|l| !l.contains('(')).count(),
+        normal.len()
+    );
+}
+
+#[test]
+fn all() {
+    let lines = drxs(&["-p", "-M", "3"]);
+    let all = drxs(&["-p", "-a", "-M", "3"]);
+    assert!(all.len() > lines.len());
+    assert!(lines.iter().all(|l| all.contains(l)));
+    // Variants are only printed when they would have been skipped
+    for (i, l) in all.iter().enumerate() {
+        assert!(!all[..i].contains(l));
+    }
+}
+
+#[test]
+fn target() {
+    let lines = drxs(&["-p", "-M", "3"]);
+    let axes = drxs(&["-p", "-M", "3", "--axis", "ud", "--axis", "fb"]);
+    assert!(!axes.is_empty());
+    assert!(axes.len() < lines.len());
+    assert!(axes.iter().all(|l| lines.contains(l)));
+
+    // Exactly one slice edge has to be left out of the slice
+    let bad = drxs(&["-p", "-M", "3", "--bad-slice", "1"]);
+    assert!(!bad.is_empty());
+    assert!(bad.iter().all(|l| !lines.contains(l)));
+}
+
+#[test]
+fn min_above_max() {
+    let out = output(&["-m", "4", "-M", "3", SCRAMBLE]);
+    assert!(!out.status.success());
+    assert!(
+        String::from_utf8(out.stderr)
+            .unwrap()
+            .contains("greater than")
+    );
+}
+
+#[test]
+fn move_set() {
+    let lines = drxs(&["-p", "-M", "5", "--moves", "U D F B R2 L2"]);
+    assert!(!lines.is_empty());
+    for line in lines {
+        assert!(
+            line.split_whitespace()
+                .all(|m| !m.starts_with(['R', 'L']) || m.ends_with('2'))
+        );
+    }
+}
+
+#[test]
+fn pipeline() {
+    let lines = drxs(&["--pipeline", "-M", "6", "-n", "20"]);
+    assert!(!lines.is_empty());
+    let mut last = 0;
+    for line in lines {
+        let (sol, n) = split_count(&line);
+        let (eo, drxs) = sol.split_once(" | ").unwrap();
+        assert_eq!(move_count(eo) + move_count(drxs), n);
+        assert!(n >= last && n <= 6);
+        last = n;
+    }
+}
+
+#[test]
+fn parallel() {
+    assert_eq!(
+        drxs(&["-p", "-M", "4", "--parallel"]),
+        drxs(&["-p", "-M", "4"])
+    );
+}
+
+#[test]
+fn json() {
+    let lines = drxs(&["-p", "-N", "-M", "3"]);
+    let json = drxs(&["--format", "json", "-N", "-M", "3"]);
+    assert_eq!(lines.len(), json.len());
+    for (line, json) in lines.iter().zip(json) {
+        let v: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(v["solution"], line.as_str());
+        let moves = v["moves"].as_array().unwrap();
+        assert_eq!(v["length"], moves.len());
+        let inverse = moves.iter().filter(|m| m["side"] == "inverse").count();
+        assert_eq!(inverse > 0, line.contains('('));
+        assert!(["ud", "fb", "lr"].contains(&v["axis"].as_str().unwrap()));
+        assert_eq!(v["bad_slice"], 0);
+        assert!(v["flipped"].is_u64());
+    }
+    assert_eq!(
+        drxs(&["--format", "json", "-c", "-M", "3"]),
+        vec![format!(r#"{{"count":{}}}"#, drxs(&["-p", "-M", "3"]).len())]
+    );
+}
+
+fn drxs_batch(args: &[&str], input: &str) -> Vec<String> {
+    with_stdin(&[args, &["--batch", "-"]].concat(), input)
+}
+
+fn with_stdin(args: &[&str], input: &str) -> Vec<String> {
+    let mut child = command(Path::new(env!("CARGO_TARGET_TMPDIR")))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let out = child.wait_with_output().unwrap();
+    assert!(out.status.success());
+    String::from_utf8(out.stdout)
+        .unwrap()
+        .lines()
+        .map(|l| l.to_string())
+        .collect()
+}
+
+#[test]
+fn batch() {
+    let lines = drxs_batch(&["-p", "-M", "3"], "R U F\n\nR X\nR U F\n");
+    let sols = drxs(&["-p", "-M", "3"]);
+    let group: Vec<_> = lines.iter().skip(1).take(sols.len()).cloned().collect();
+    assert_eq!(lines[0], "# 1: R U F");
+    assert_eq!(group, sols);
+    assert!(lines.contains(&"# 4: R U F".to_string()));
+    assert!(lines.iter().any(|l| l.starts_with("# 3 scrambles in")));
+    assert!(lines.iter().any(|l| l.ends_with("1 errors, 0 unsolved")));
+    assert!(lines.contains(&"# mean optimal length: 2.00".to_string()));
+    assert!(lines.contains(&"# optimal length 2: 2".to_string()));
+    // With a minimum length, the shortest solutions found may not be optimal
+    let lines = drxs_batch(&["-c", "-m", "3", "-M", "3"], "R U F\n");
+    assert!(lines.contains(&"# mean shortest found length: 3.00".to_string()));
+    assert!(lines.contains(&"# shortest found length 3: 1".to_string()));
+
+    let json = drxs_batch(&["--format", "json", "-M", "3"], "R U F\nR X\n");
+    let json: Vec<serde_json::Value> = json
+        .iter()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(json.len(), 3);
+    assert_eq!(json[0]["solutions"].as_array().unwrap().len(), sols.len());
+    assert_eq!(json[0]["optimal"], 2);
+    assert_eq!(json[1]["line"], 2);
+    assert!(json[1]["error"].is_string());
+    assert_eq!(json[2]["summary"]["scrambles"], 2);
+    assert_eq!(json[2]["summary"]["errors"], 1);
+    assert_eq!(json[2]["summary"]["mean_optimal"], 2.0);
+}
+
+#[test]
+fn analyse() {
+    let args = [
+        "analyse",
+        "-n",
+        "6",
+        "--seed",
+        "7",
+        "--eo",
+        "ud",
+        "--bad-slice",
+        "0",
+    ];
+    let lines = run(&args);
+    assert_eq!(run(&args), lines);
+    assert!(lines[0].starts_with("0 bad slice edges, mean "));
+    assert!(lines[0].ends_with(" over 6 states"));
+    let counts: usize = lines[1..]
+        .iter()
+        .map(|l| l.rsplit_once(' ').unwrap().1.parse::<usize>().unwrap())
+        .sum();
+    assert_eq!(counts, 6);
+
+    let lines = run(&["analyse", "-n", "2", "--bad-slice", "1", "--bad-slice", "3"]);
+    assert!(lines.contains(&String::new()));
+    assert!(lines.iter().any(|l| l.starts_with("1 bad slice edges")));
+    assert!(lines.iter().any(|l| l.starts_with("3 bad slice edges")));
+}
+
+#[test]
+fn reduce_symmetry() {
+    let sols = run(&["-p", "-M", "3", "U2 D2"]);
+    let reduced = run(&["-p", "-M", "3", "--reduce-symmetry", "U2 D2"]);
+    assert!(!reduced.is_empty() && reduced.len() < sols.len());
+    assert!(reduced.iter().all(|sol| sols.contains(sol)));
+    // R U F has no symmetries
+    assert_eq!(
+        drxs(&["-p", "-M", "3", "--reduce-symmetry"]),
+        drxs(&["-p", "-M", "3"])
+    );
+}
+
+#[test]
+fn repl() {
+    let lines = with_stdin(
+        &["repl"],
+        "scramble R U F\nsolve -p -M 3\napply F'\nsolve -p -M 1\nundo\nundo\nshow\nbad\n",
+    );
+    let sols = drxs(&["-p", "-M", "3"]);
+    assert_eq!(lines[..sols.len()], sols);
+    // After F' only the rest of the solutions starting with F' are left
+    let rest = &lines[sols.len()..];
+    assert_eq!(rest[0], "U");
+    assert_eq!(rest[1], "error: there is nothing to undo");
+    assert_eq!(rest[2], "scramble: R U F");
+    assert!(rest[4].starts_with("axis"));
+    assert!(
+        rest.last()
+            .unwrap()
+            .starts_with("error: unknown command `bad`")
+    );
+}
+
+#[test]
+fn describe() {
+    let lines = run(&["describe", "R"]);
+    assert_eq!(lines.len(), 4);
+    assert!(lines[1].starts_with("ud") && lines[1].ends_with(" -"));
+    assert!(lines[3].starts_with("lr") && lines[3].ends_with("oriented corners, 0 bad slice"));
+
+    let json = run(&["describe", "R", "--format", "json"]);
+    let v: serde_json::Value = serde_json::from_str(&json[0]).unwrap();
+    let axes = v["axes"].as_array().unwrap();
+    assert_eq!(axes.len(), 3);
+    assert_eq!(axes[2]["axis"], "lr");
+    assert_eq!(axes[2]["twisted_corners"], 0);
+    assert_eq!(axes[2]["slice_edges"], 4);
+    assert_eq!(axes[2]["drxs"][0]["bad_slice"], 0);
+    assert_eq!(axes[0]["twisted_corners"], 4);
+    assert!(axes[0]["drxs"].as_array().unwrap().is_empty());
+}
+
+#[test]
+fn budget() {
+    let sols = drxs(&["-p", "-M", "5"]);
+    let out = output(&["-p", "-M", "5", "--node-limit", "50", SCRAMBLE]);
+    assert!(out.status.success());
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    let limited: Vec<_> = stdout.lines().collect();
+    assert!(limited.len() < sols.len());
+    assert!(sols.starts_with(&limited.iter().map(|l| l.to_string()).collect::<Vec<_>>()));
+    assert!(
+        String::from_utf8(out.stderr)
+            .unwrap()
+            .starts_with("out of budget")
+    );
+    // A generous budget changes nothing
+    assert_eq!(drxs(&["-p", "-M", "5", "--time-limit", "60000"]), sols);
+}
+
+#[test]
+fn subset() {
+    let lines = drxs(&["-p", "-M", "3"]);
+    let subsets = drxs(&["-p", "-M", "3", "--subset"]);
+    assert_eq!(lines.len(), subsets.len());
+    for (line, subset) in lines.iter().zip(&subsets) {
+        let (sol, subset) = subset.split_once("  ").unwrap();
+        assert_eq!(sol, line);
+        assert!(subset == "-" || subset.ends_with('e'), "{subset}");
+    }
+    assert!(subsets.contains(&"F' U  4a1 4e".to_string()));
+    assert!(subsets.contains(&"F' U' R  0c0 0e".to_string()));
+    // A dr-xs is labelled with the subset of the dr it leads to, unless its corners are twisted
+    let subsets = drxs(&["-M", "3", "--bad-slice", "2", "--subset"]);
+    assert!(subsets.contains(&"F B2 D (3)  4a1 3e, 2 bad slice".to_string()));
+    assert!(subsets.contains(&"B' R' (2)  -".to_string()));
+    let json = drxs(&["--format", "json", "-M", "2"]);
+    let v: serde_json::Value = serde_json::from_str(&json[0]).unwrap();
+    assert_eq!(v["subset"], "4a1 4e");
+}
+
+#[test]
+fn finish() {
+    let lines = drxs(&["-M", "3", "--bad-slice", "2"]);
+    // Bad slice edges can't be put back while keeping the dr
+    let finishes = drxs(&["-M", "3", "--bad-slice", "2", "--finish", "dr"]);
+    assert_eq!(lines.len(), finishes.len());
+    for (line, finish) in lines.iter().zip(&finishes) {
+        assert_eq!(*finish, format!("{line}  + -"));
+    }
+    // A dr is already finished, and goes on to htr with the moves of its axis
+    assert!(drxs(&["-M", "2", "--finish", "dr"]).contains(&"F' U (2)  + (0)".to_string()));
+    assert!(
+        drxs(&["-M", "2", "--finish", "htr"]).contains(&"F' U (2)  + R L' B2 L (4)".to_string())
+    );
+
+    let any_moves = ["--finish", "dr", "--finish-any-moves"];
+    let finishes = drxs(&[&["-M", "3", "--bad-slice", "2"], &any_moves[..]].concat());
+    assert_eq!(lines.len(), finishes.len());
+    for (line, finish) in lines.iter().zip(&finishes) {
+        let (sol, finish) = finish.split_once("  + ").unwrap();
+        assert_eq!(sol, line);
+        assert!(finish == "-" || finish.ends_with(')'), "{finish}");
+    }
+    assert!(finishes.contains(&"F B2 D (3)  + U R2 L2 D (4)".to_string()));
+    let json = drxs(
+        &[
+            &["--format", "json", "-M", "3", "--bad-slice", "2"],
+            &any_moves[..],
+        ]
+        .concat(),
+    );
+    let v: serde_json::Value = serde_json::from_str(&json[2]).unwrap();
+    assert_eq!(v["finish"], "U R2 L2 D");
+
+    // Solutions ending on the inverse are finished on the inverse
+    let finishes = drxs(&[&["-N", "-M", "3", "--bad-slice", "2"], &any_moves[..]].concat());
+    for line in &finishes {
+        let (sol, finish) = line.split_once("  + ").unwrap();
+        let (moves, _) = sol.rsplit_once(" (").unwrap();
+        if moves.ends_with(')') {
+            assert!(finish == "-" || finish.starts_with('('), "{line}");
+        }
+    }
+    assert!(finishes.contains(&"B (R) (2)  + (U F B) (3)".to_string()));
+    assert!(finishes.contains(&"F B2 D (3)  + U R2 L2 D (4)".to_string()));
+}
+
+#[test]
+fn prefix() {
+    let lines = drxs(&["-M", "2", "--prefix", "F'"]);
+    assert!(lines.contains(&"F' U (2)".to_string()));
+    assert!(lines.iter().all(|l| l.starts_with("F' ")));
+    // Moves on the inverse carry on on the inverse
+    let lines = drxs(&["-M", "1", "--prefix", "(R)"]);
+    assert_eq!(lines, ["(R U) (2)"]);
+}
+
+#[test]
+fn table_cache() {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("cli-table-cache");
+    let _ = std::fs::remove_dir_all(&dir);
+    let solve = || {
+        let out = output_in(&dir, &["-M", "3", SCRAMBLE]);
+        assert!(out.status.success());
+        out.stdout
+    };
+    let built = solve();
+    let tables = std::fs::read_dir(&dir).unwrap().count();
+    assert!(tables > 0);
+    // The second run loads the tables instead, and finds the same solutions
+    assert_eq!(solve(), built);
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), tables);
+    assert_eq!(
+        String::from_utf8(built)
+            .unwrap()
+            .lines()
+            .collect::<Vec<_>>(),
+        drxs(&["-M", "3"])
+    );
+}
+
+#[test]
+fn premoves() {
+    let lines = drxs(&["-M", "3", "--premoves", "1"]);
+    assert_eq!(lines[..2], ["(R) F (2)", "(R') F (2)"]);
+    assert!(lines.iter().all(|l| l.starts_with('(')));
+    let pairs = drxs(&["-M", "3", "--premoves", "2"]);
+    assert!(pairs.len() > lines.len());
+    assert!(lines.iter().all(|l| pairs.contains(l)));
+    let json = drxs(&["--format", "json", "-M", "2", "--premoves", "1"]);
+    let v: serde_json::Value = serde_json::from_str(&json[0]).unwrap();
+    assert_eq!(v["premoves"], "R");
+    assert_eq!(v["moves"][0]["side"], "inverse");
+}
+
+#[test]
+fn ranking() {
+    let lines = drxs(&["-M", "3"]);
+    let sorted = drxs(&["-M", "3", "--sort"]);
+    assert_eq!(lines.len(), sorted.len());
+    let score = |line: &String| {
+        line.rsplit_once("score ")
+            .unwrap()
+            .1
+            .parse::<f64>()
+            .unwrap()
+    };
+    assert!(sorted.is_sorted_by(|a, b| score(a) <= score(b)));
+    // The zero corner case of F' U' R beats the shorter F' U
+    assert_eq!(
+        drxs(&["-M", "3", "--top", "2"]),
+        ["F' U' R (3)  score 2.5", "F' U (2)  score 3"]
+    );
+    assert_eq!(
+        drxs(&["-M", "3", "--top", "1", "--score", "length=10"]),
+        ["F' U (2)  score 21"]
+    );
+}
@@ -1,9 +1,17 @@
-use cube_lib::cube333::{
-    moves::{Htm, Move333, MoveGenerator, Move333Type},
-    CubieCube,
+use clap::{Parser, Subcommand};
+use cube_lib::{
+    cube333::{
+        moves::{Move333, Move333Type},
+        CubieCube,
+    },
+    moves::{MoveSequence, NissSequence},
 };
-use rand::seq::IteratorRandom;
-use std::collections::{HashSet, HashMap, VecDeque};
+use std::{
+    collections::{HashSet, VecDeque},
+    process,
+    str::FromStr,
+};
+use table_cache::{Table, TableCache};
 
 const U1: Move333 = Move333 {
     ty: Move333Type::U,
@@ -27,7 +35,7 @@ const F1: Move333 = Move333 {
 };
 const B1: Move333 = Move333 {
     ty: Move333Type::B,
-    count: 2,
+    count: 1,
 };
 const U2: Move333 = Move333 {
     ty: Move333Type::U,
@@ -78,47 +86,107 @@ const B3: Move333 = Move333 {
     count: 3,
 };
 
-pub struct Fr;
-pub struct Htr;
-
-impl MoveGenerator for Fr {
-    const SIZE: usize = 4;
-    const MOVE_LIST: &'static [Move333] = &[R2, L2, F2, B2];
+const HTM: &[Move333] = &[
+    U1, U2, U3, D1, D2, D3, R1, R2, R3, L1, L2, L3, F1, F2, F3, B1, B2, B3,
+];
+const DR: &[Move333] = &[U1, U2, U3, D1, D2, D3, R2, L2, F2, B2];
+const HTR: &[Move333] = &[U2, D2, R2, L2, F2, B2];
+const FR: &[Move333] = &[R2, L2, F2, B2];
+
+/// The moves that a set of states is generated with: `htm`, `dr` (<U, D, R2, L2, F2, B2>), `htr`
+/// (half turns only), `fr` (<R2, L2, F2, B2>) or a list of moves such as "R2 U".
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Generator {
+    /// The name the generator was given by, or its moves written like `R2Ui` for a list of moves,
+    /// which can be used in file names.
+    name: String,
+    moves: Vec<Move333>,
 }
 
-impl MoveGenerator for Htr {
-    const SIZE: usize = 6;
-    const MOVE_LIST: &'static [Move333] = &[U2, D2, R2, L2, F2, B2];
+impl FromStr for Generator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let moves = match s {
+            "htm" => HTM.to_vec(),
+            "dr" => DR.to_vec(),
+            "htr" => HTR.to_vec(),
+            "fr" => FR.to_vec(),
+            _ => {
+                let moves = s
+                    .parse::<MoveSequence<Move333>>()
+                    .map_err(|_| {
+                        format!(
+                            "unknown generator `{s}`, expected htm, dr, htr, fr or a list of moves"
+                        )
+                    })?
+                    .0;
+                let name = moves.iter().map(|m| m.to_string().replace('\'', "i"));
+                return Generator::new(name.collect(), moves);
+            }
+        };
+        Generator::new(s.to_string(), moves)
+    }
 }
 
-#[derive(Debug)]
-struct GeneratorSet {
-    set: HashSet<CubieCube>,
+impl Generator {
+    fn new(name: String, moves: Vec<Move333>) -> Result<Generator, String> {
+        if moves.is_empty() {
+            return Err("a generator needs at least one move".to_string());
+        }
+        Ok(Generator { name, moves })
+    }
 }
 
-impl GeneratorSet {
-    fn from_generator_trait<G: MoveGenerator>(cube: CubieCube) -> Self {
-        // BFS on solved cube
-        let mut set = HashSet::new();
-
-        let mut stack = vec![cube];
-
-        while let Some(cube) = stack.pop() {
-            set.insert(cube.clone());
+/// A set of states to solve to: `solved`, the `slice` states, or every state that a generator
+/// reaches from solved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum SetName {
+    Solved,
+    Slice,
+    Generated(Generator),
+}
 
-            for &mv in G::MOVE_LIST {
-                let new_cube = cube.make_move(mv);
-                if !set.contains(&new_cube) {
-                    stack.push(new_cube);
-                }
-            }
+impl FromStr for SetName {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "solved" => Ok(SetName::Solved),
+            "slice" => Ok(SetName::Slice),
+            _ => s
+                .parse()
+                .map(SetName::Generated)
+                .map_err(|_| format!("unknown set `{s}`, expected solved, slice or a generator")),
         }
+    }
+}
 
-        GeneratorSet { set }
+impl SetName {
+    fn name(&self) -> &str {
+        match self {
+            SetName::Solved => "solved",
+            SetName::Slice => "slice",
+            SetName::Generated(generator) => &generator.name,
+        }
     }
+}
+
+#[derive(Debug)]
+struct GeneratorSet {
+    set: HashSet<CubieCube>,
+}
 
-    fn from_generator_trait_print_solns<G: MoveGenerator>(cube: CubieCube) -> Self {
-        // BFS on solved cube
+impl GeneratorSet {
+    /// Every state that `generator` reaches from `cube`, in at most `max_depth` moves if given.
+    /// The states are found with a BFS, and `visit` is called with a shortest sequence to each of
+    /// them in the order they are found.
+    fn from_generator(
+        cube: CubieCube,
+        generator: &Generator,
+        max_depth: Option<usize>,
+        mut visit: impl FnMut(&[Move333]),
+    ) -> Self {
         let mut set = HashSet::new();
 
         let mut queue = VecDeque::new();
@@ -126,9 +194,12 @@ impl GeneratorSet {
         set.insert(cube);
 
         while let Some((cube, soln)) = queue.pop_front() {
-            println!("{}", soln.iter().cloned().map(|mv| format!("{mv:?}")).collect::<Vec<_>>().join(" "));
+            visit(&soln);
+            if max_depth.is_some_and(|d| soln.len() >= d) {
+                continue;
+            }
 
-            for &mv in G::MOVE_LIST {
+            for &mv in &generator.moves {
                 let new_cube = cube.make_move(mv);
                 if !set.contains(&new_cube) {
                     set.insert(new_cube.clone());
@@ -142,6 +213,16 @@ impl GeneratorSet {
         GeneratorSet { set }
     }
 
+    fn from_name(name: &SetName) -> Self {
+        match name {
+            SetName::Solved => Self::solved_set(),
+            SetName::Slice => Self::slice_set(),
+            SetName::Generated(generator) => {
+                Self::from_generator(CubieCube::SOLVED, generator, None, |_| {})
+            }
+        }
+    }
+
     fn solved_set() -> Self {
         let mut set = HashSet::new();
         set.insert(CubieCube::SOLVED);
@@ -257,78 +338,290 @@ impl GeneratorSet {
         GeneratorSet { set }
     }
 
-    fn product<G: MoveGenerator>(&self) -> Self {
+    /// Every state that `generator` reaches from a state in the set.
+    fn product(&self, generator: &Generator) -> Self {
         let mut set = HashSet::new();
-        for cube in self.set.clone().into_iter() {
-            let tmpset = Self::from_generator_trait::<G>(cube);
-            for cube in tmpset.set.into_iter() {
-                set.insert(cube);
+        for cube in self.set.iter() {
+            // Every move can be undone by repeating it, so a state which has already been reached
+            // reaches the same states as the one it was reached from
+            if set.contains(cube) {
+                continue;
             }
+            set.extend(Self::from_generator(cube.clone(), generator, None, |_| {}).set);
         }
         GeneratorSet { set }
     }
 }
 
-fn search<G: MoveGenerator>(
+/// The version of the sets kept in the table cache, to be bumped whenever the way they are built or
+/// stored changes.
+const TABLE_VERSION: u32 = 1;
+/// The number of bytes that a state takes up in a `StateSet`.
+const STATE_LEN: usize = 20;
+
+/// A state as bytes: the piece and twist of each corner, then the piece and flip of each edge.
+fn encode(c: &CubieCube) -> [u8; STATE_LEN] {
+    let mut bytes = [0; STATE_LEN];
+    let corners = c.cp.iter().zip(&c.co).map(|(&p, &t)| p as u8 * 3 + t as u8);
+    let edges = c.ep.iter().zip(&c.eo).map(|(&p, &f)| p as u8 * 2 + f as u8);
+    for (byte, piece) in bytes.iter_mut().zip(corners.chain(edges)) {
+        *byte = piece;
+    }
+    bytes
+}
+
+/// A set of states as their encodings, see `encode`, in sorted order so that it can be searched
+/// without being turned back into cubes, and kept in a `TableCache`.
+struct StateSet {
+    states: Table,
+}
+
+impl StateSet {
+    fn new(set: &GeneratorSet) -> StateSet {
+        let mut states: Vec<_> = set.set.iter().map(encode).collect();
+        states.sort_unstable();
+        StateSet {
+            states: Table::from(states.concat()),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.states.len() / STATE_LEN
+    }
+
+    fn contains(&self, c: &CubieCube) -> bool {
+        let (states, _) = self.states.as_chunks::<STATE_LEN>();
+        states.binary_search(&encode(c)).is_ok()
+    }
+}
+
+fn search(
     cube: CubieCube,
-    set: &GeneratorSet,
+    generator: &Generator,
+    set: &StateSet,
     depth: usize,
 ) -> Option<Vec<Move333>> {
     if depth == 0 {
-        if set.set.contains(&cube) {
+        if set.contains(&cube) {
             return Some(vec![]);
         }
         return None;
     }
-    for mv in G::MOVE_LIST {
-        let new_cube = cube.make_move(*mv);
-        if let Some(mut seq) = search::<G>(new_cube, set, depth - 1) {
-            seq.push(*mv);
+    for &mv in &generator.moves {
+        let new_cube = cube.make_move(mv);
+        if let Some(mut seq) = search(new_cube, generator, set, depth - 1) {
+            seq.push(mv);
             return Some(seq);
         }
     }
     None
 }
 
-fn solve<G: MoveGenerator>(cube: CubieCube, set: &GeneratorSet) -> Vec<Move333> {
-    for depth in 0..32 {
-        if let Some(mut sol) = search::<G>(cube.clone(), set, depth) {
+/// A shortest sequence of `generator` moves taking `cube` to a state in `set`, if there is one
+/// with at most `max_depth` moves.
+fn solve(
+    cube: CubieCube,
+    generator: &Generator,
+    set: &StateSet,
+    max_depth: usize,
+) -> Option<Vec<Move333>> {
+    for depth in 0..=max_depth {
+        if let Some(mut sol) = search(cube.clone(), generator, set, depth) {
             sol.reverse();
-            return sol;
+            return Some(sol);
         }
     }
-    panic!("Cube took way too long to solve");
+    None
+}
+
+/// Moves written like `R2 U2 (2)`.
+fn show(moves: &[Move333]) -> String {
+    if moves.is_empty() {
+        return "(0)".to_string();
+    }
+    format!("{} ({})", MoveSequence(moves.to_vec()), moves.len())
+}
+
+/// Enumerates, counts and solves with the states reached by move generators
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print a shortest sequence to every state the generator reaches, in order of length
+    Enumerate(GenerateArgs),
+    /// Print the number of states the generator reaches
+    Count(GenerateArgs),
+    /// Print a shortest sequence of the generator taking a scramble to a state in a set
+    Solve(SolveArgs),
+    /// Print the number of states the generators reach from a state in a set
+    Product(ProductArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct GenerateArgs {
+    /// The generator: htm, dr, htr, fr or a list of moves such as "R2 U"
+    generator: Generator,
+
+    /// The scramble of the state to start from instead of solved. Niss brackets are allowed
+    #[arg(long)]
+    start: Option<NissSequence<Move333>>,
+
+    /// Only go this many moves from the start
+    #[arg(short('M'))]
+    max: Option<usize>,
+}
+
+impl GenerateArgs {
+    fn generate(&self, visit: impl FnMut(&[Move333])) -> GeneratorSet {
+        let cube = match &self.start {
+            Some(start) => CubieCube::SOLVED.make_niss_moves(start.clone()),
+            None => CubieCube::SOLVED,
+        };
+        GeneratorSet::from_generator(cube, &self.generator, self.max, visit)
+    }
+}
+
+#[derive(clap::Args, Debug)]
+struct SolveArgs {
+    /// The scramble to solve. Niss brackets are allowed
+    scramble: NissSequence<Move333>,
+
+    /// The generator to solve with: htm, dr, htr, fr or a list of moves such as "R2 U"
+    #[arg(long, default_value = "htm")]
+    generator: Generator,
+
+    /// The set to solve to: solved, slice, or a generator for every state it reaches from solved
+    #[arg(long, default_value = "solved")]
+    target: SetName,
+
+    /// Add the states this generator reaches from the target set to it. May be given more than
+    /// once, which adds the states of each generator in turn
+    #[arg(long)]
+    times: Vec<Generator>,
+
+    /// Maximum solution length
+    #[arg(short('M'), default_value_t = 32)]
+    max: usize,
+}
+
+#[derive(clap::Args, Debug)]
+struct ProductArgs {
+    /// The set to start from: solved, slice, or a generator for every state it reaches from solved
+    set: SetName,
+
+    /// The generators to take the product with, in turn
+    #[arg(required = true)]
+    generators: Vec<Generator>,
+}
+
+/// The states `set` with the states of each of `generators` added in turn.
+fn product(set: &SetName, generators: &[Generator]) -> GeneratorSet {
+    (generators.iter()).fold(GeneratorSet::from_name(set), |set, generator| {
+        set.product(generator)
+    })
+}
+
+/// Like `product`, but kept in `cache` if there is one, so that the states are only found once.
+/// The length of a table has to be known before it can be loaded, so the number of bytes the
+/// states take up is kept in a table of its own.
+fn cached_product(cache: Option<&TableCache>, set: &SetName, generators: &[Generator]) -> StateSet {
+    let Some(cache) = cache else {
+        return StateSet::new(&product(set, generators));
+    };
+    let mut name = format!("generator-solver-{}", set.name());
+    for generator in generators {
+        name += &format!("-{}", generator.name);
+    }
+
+    let mut built = None;
+    let len = cache.get(&format!("{name}-len"), TABLE_VERSION, 8, || {
+        let states = StateSet::new(&product(set, generators)).states.to_vec();
+        let len = (states.len() as u64).to_le_bytes().to_vec();
+        built = Some(states);
+        len
+    });
+    let len = u64::from_le_bytes(len[..].try_into().unwrap()) as usize;
+    let states = cache.get(&name, TABLE_VERSION, len, || {
+        built.unwrap_or_else(|| StateSet::new(&product(set, generators)).states.to_vec())
+    });
+    StateSet { states }
 }
 
 fn main() {
-    let solved_set = GeneratorSet::solved_set();
-    let slice_set = GeneratorSet::slice_set();
-    let fr_set = GeneratorSet::from_generator_trait::<Fr>(CubieCube::SOLVED);
-    let fr_slice_set = slice_set.product::<Fr>();
-    //println!("Floppy reduction set generated with {} elements", fr_set.set.len());
-    let htr_set = GeneratorSet::from_generator_trait_print_solns::<Htr>(CubieCube::SOLVED);
-    //println!("HTR set generated with {} elements", htr_set.set.len());
-
-    /*
-    let mut cube = CubieCube::SOLVED;
-
-    cube = cube.make_move(B2);
-    cube = cube.make_move(L2);
-    cube = cube.make_move(B2);
-    cube = cube.make_move(R2);
-    cube = cube.make_move(L2);
-    cube = cube.make_move(B2);
-    cube = cube.make_move(L2);
-    cube = cube.make_move(F2);
-
-    let seq = solve::<Htm>(cube, &solved_set);
-    println!("{:?}", seq);
-    */
-
-    /*
-    for cube in htr_set.set.iter() {
-        let seq = solve::<Htr>(cube.clone(), &fr_slice_set);
-        println!("{:?}", seq);
+    let cache = TableCache::default_dir().map(TableCache::new);
+    match Args::parse().command {
+        Command::Enumerate(args) => {
+            args.generate(|moves| println!("{}", show(moves)));
+        }
+        // Only the whole set reached from solved is worth keeping
+        Command::Count(args) if args.start.is_none() && args.max.is_none() => {
+            let set = SetName::Generated(args.generator);
+            println!("{}", cached_product(cache.as_ref(), &set, &[]).len());
+        }
+        Command::Count(args) => println!("{}", args.generate(|_| {}).set.len()),
+        Command::Solve(args) => {
+            let target = cached_product(cache.as_ref(), &args.target, &args.times);
+            let cube = CubieCube::SOLVED.make_niss_moves(args.scramble);
+            match solve(cube, &args.generator, &target, args.max) {
+                Some(sol) => println!("{}", show(&sol)),
+                None => {
+                    eprintln!("no solution in {} moves or fewer", args.max);
+                    process::exit(1);
+                }
+            }
+        }
+        Command::Product(args) => {
+            let set = cached_product(cache.as_ref(), &args.set, &args.generators);
+            println!("{}", set.len());
+        }
+    }
+}
+
+#[test]
+fn generated_sets() {
+    let generate = |generator: &str, max_depth| {
+        let generator = generator.parse().unwrap();
+        GeneratorSet::from_generator(CubieCube::SOLVED, &generator, max_depth, |_| {})
+            .set
+            .len()
+    };
+    assert_eq!(generate("fr", None), 192);
+    assert_eq!(generate("R2 U2", None), 12);
+    assert_eq!(generate("htm", Some(2)), 1 + 18 + 243);
+    assert!("R3".parse::<Generator>().is_err());
+
+    let fr = "fr".parse().unwrap();
+    assert_eq!(product(&SetName::Slice, &[fr]).set.len(), 2304);
+
+    let solve = |scramble: &str, generator: &str, target: &str| {
+        let cube = CubieCube::SOLVED.make_niss_moves(scramble.parse().unwrap());
+        let target = StateSet::new(&GeneratorSet::from_name(&target.parse().unwrap()));
+        solve(cube, &generator.parse().unwrap(), &target, 3).map(|sol| show(&sol))
+    };
+    assert_eq!(solve("R U", "htm", "solved").as_deref(), Some("U' R' (2)"));
+    assert_eq!(solve("R2 U2", "htr", "fr").as_deref(), Some("U2 (1)"));
+    assert_eq!(solve("R U", "htr", "solved"), None);
+}
+
+#[test]
+fn cached_sets() {
+    let dir = std::env::temp_dir().join(format!("generator-solver-{}", process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let cache = TableCache::new(&dir);
+    let fr: [Generator; 1] = ["fr".parse().unwrap()];
+
+    for _ in 0..2 {
+        let set = cached_product(Some(&cache), &SetName::Slice, &fr);
+        assert_eq!(set.len(), 2304);
+        let cube = CubieCube::SOLVED.make_niss_moves("R2 F2 U D'".parse().unwrap());
+        assert!(!set.contains(&cube));
+        assert!(set.contains(&cube.make_move(U3).make_move(D1)));
     }
-    */
+    assert!(cache.path("generator-solver-slice-fr").exists());
+    let _ = std::fs::remove_dir_all(&dir);
 }
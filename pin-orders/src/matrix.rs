@@ -122,7 +122,7 @@ impl CompletedMatrix {
     }
 
     pub fn to_echelon(mut self) -> CompletedMatrix {
-        if self.0.len() == 0 {
+        if self.0.is_empty() {
             return self;
         }
 
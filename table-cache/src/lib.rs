@@ -0,0 +1,326 @@
+//! Keeps pruning tables on disk between runs, so that they only have to be built once.
+//!
+//! A table is a slice of bytes with a name and a version, which the solver that uses it bumps
+//! whenever the way it builds the table changes. Each table is stored in its own file in the cache
+//! directory, after a header with the version, the length and a checksum of the table. Loading a
+//! table checks all of these, and a table which is missing, stale or corrupt is built again and
+//! replaces the file.
+//!
+//! Files are only ever replaced as a whole by renaming a new file over them, never written in
+//! place, so a table that is loaded can be memory mapped and shared between processes.
+
+use std::{
+    env,
+    fs::{self, File},
+    io::{self, Write},
+    ops::Deref,
+    path::{Path, PathBuf},
+    process,
+};
+
+/// The start of every table file.
+const MAGIC: [u8; 8] = *b"CUBETBL\0";
+/// The version of the file layout, which is separate from the versions of the tables.
+const FORMAT_VERSION: u32 = 1;
+/// The magic, the format version, the table version, the length and the checksum.
+const HEADER_LEN: usize = 32;
+
+/// A directory of tables.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableCache {
+    dir: PathBuf,
+}
+
+impl TableCache {
+    pub fn new(dir: impl Into<PathBuf>) -> TableCache {
+        TableCache { dir: dir.into() }
+    }
+
+    /// The cache directory shared by the programs in this repository. This is `$CUBE_TABLE_CACHE`
+    /// if it is set, or a `cubing-stuff` directory in the user's cache directory otherwise.
+    /// Setting `CUBE_TABLE_CACHE` to nothing turns the cache off, and None is returned.
+    pub fn default_dir() -> Option<PathBuf> {
+        if let Some(dir) = env::var_os("CUBE_TABLE_CACHE") {
+            return (!dir.is_empty()).then(|| PathBuf::from(dir));
+        }
+        let cache = match env::var_os("XDG_CACHE_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(env::var_os("HOME")?).join(".cache"),
+        };
+        Some(cache.join("cubing-stuff"))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The file that the table `name` is stored in.
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{name}.table"))
+    }
+
+    /// The table `name`, which should have `len` bytes and be at version `version`. If there
+    /// isn't a valid copy in the cache, it is made with `build` and stored for next time.
+    ///
+    /// The cache only saves time, so a table is still returned if it can't be stored, for
+    /// example because the directory is read only.
+    pub fn get(
+        &self,
+        name: &str,
+        version: u32,
+        len: usize,
+        build: impl FnOnce() -> Vec<u8>,
+    ) -> Table {
+        let path = self.path(name);
+        if let Some(table) = load(&path, version, len) {
+            return table;
+        }
+
+        let data = build();
+        assert_eq!(
+            data.len(),
+            len,
+            "the table `{name}` was built with the wrong length"
+        );
+        if store(&path, version, &data).is_err() {
+            return Table::from(data);
+        }
+        // Map the new file, so that the memory is shared with any other process using the table
+        load(&path, version, len).unwrap_or_else(|| Table::from(data))
+    }
+}
+
+/// A table, either mapped from its file or held in memory.
+pub struct Table {
+    data: Data,
+}
+
+enum Data {
+    #[cfg(unix)]
+    Mapped(mmap::Mmap),
+    Owned(Vec<u8>),
+}
+
+impl Deref for Table {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match &self.data {
+            #[cfg(unix)]
+            Data::Mapped(map) => &map[HEADER_LEN..],
+            Data::Owned(data) => data,
+        }
+    }
+}
+
+impl From<Vec<u8>> for Table {
+    fn from(data: Vec<u8>) -> Self {
+        Table {
+            data: Data::Owned(data),
+        }
+    }
+}
+
+/// 64 bit FNV-1a, which is quick and plenty to notice a damaged file.
+fn checksum(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn header(version: u32, data: &[u8]) -> [u8; HEADER_LEN] {
+    let mut header = [0; HEADER_LEN];
+    header[..8].copy_from_slice(&MAGIC);
+    header[8..12].copy_from_slice(&FORMAT_VERSION.to_le_bytes());
+    header[12..16].copy_from_slice(&version.to_le_bytes());
+    header[16..24].copy_from_slice(&(data.len() as u64).to_le_bytes());
+    header[24..].copy_from_slice(&checksum(data).to_le_bytes());
+    header
+}
+
+/// Whether `file` holds a valid table with the version and length asked for.
+fn is_valid(file: &[u8], version: u32, len: usize) -> bool {
+    file.len() == HEADER_LEN + len && file[..HEADER_LEN] == header(version, &file[HEADER_LEN..])
+}
+
+/// The table stored at `path`, if it is there and valid.
+fn load(path: &Path, version: u32, len: usize) -> Option<Table> {
+    let file = File::open(path).ok()?;
+    if file.metadata().ok()?.len() != (HEADER_LEN + len) as u64 {
+        return None;
+    }
+
+    #[cfg(unix)]
+    let data = Data::Mapped(mmap::Mmap::map(&file, HEADER_LEN + len).ok()?);
+    #[cfg(not(unix))]
+    let data = Data::Owned(fs::read(path).ok()?);
+
+    let valid = match &data {
+        #[cfg(unix)]
+        Data::Mapped(map) => is_valid(map, version, len),
+        Data::Owned(data) => is_valid(data, version, len),
+    };
+    if !valid {
+        return None;
+    }
+    Some(match data {
+        #[cfg(unix)]
+        mapped @ Data::Mapped(_) => Table { data: mapped },
+        Data::Owned(mut data) => {
+            data.drain(..HEADER_LEN);
+            Table::from(data)
+        }
+    })
+}
+
+/// Writes a table to `path`, replacing whatever was there all at once.
+fn store(path: &Path, version: u32, data: &[u8]) -> io::Result<()> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    fs::create_dir_all(dir)?;
+    // Another process may be writing the same table, so each writes to its own file first
+    let tmp = path.with_extension(format!("tmp{}", process::id()));
+    let written = (|| {
+        let mut file = File::create(&tmp)?;
+        file.write_all(&header(version, data))?;
+        file.write_all(data)?;
+        file.sync_all()
+    })();
+    match written.and_then(|()| fs::rename(&tmp, path)) {
+        Ok(()) => Ok(()),
+        Err(e) => {
+            let _ = fs::remove_file(&tmp);
+            Err(e)
+        }
+    }
+}
+
+#[cfg(unix)]
+mod mmap {
+    use std::{fs::File, io, ops::Deref, os::fd::AsRawFd, ptr, slice};
+
+    /// A read only memory map of a whole file.
+    pub struct Mmap {
+        ptr: *mut libc::c_void,
+        len: usize,
+    }
+
+    // The map is never written to, so it can be read from any thread
+    unsafe impl Send for Mmap {}
+    unsafe impl Sync for Mmap {}
+
+    impl Mmap {
+        /// Maps the first `len` bytes of `file`, which has to be at least that long.
+        pub fn map(file: &File, len: usize) -> io::Result<Mmap> {
+            // SAFETY: a private read only mapping of an open file. The table files are only
+            // replaced by renaming, never changed in place, so the mapping stays valid.
+            let ptr = unsafe {
+                libc::mmap(
+                    ptr::null_mut(),
+                    len,
+                    libc::PROT_READ,
+                    libc::MAP_PRIVATE,
+                    file.as_raw_fd(),
+                    0,
+                )
+            };
+            if ptr == libc::MAP_FAILED {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Mmap { ptr, len })
+        }
+    }
+
+    impl Deref for Mmap {
+        type Target = [u8];
+
+        fn deref(&self) -> &[u8] {
+            // SAFETY: the mapping is `len` bytes long and lives as long as `self`
+            unsafe { slice::from_raw_parts(self.ptr as *const u8, self.len) }
+        }
+    }
+
+    impl Drop for Mmap {
+        fn drop(&mut self) {
+            // SAFETY: the mapping was made by `map` and isn't used after this
+            unsafe {
+                libc::munmap(self.ptr, self.len);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+use std::cell::Cell;
+
+#[cfg(test)]
+fn test_cache(name: &str) -> TableCache {
+    let dir = env::temp_dir().join(format!("table-cache-{name}-{}", process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    TableCache::new(dir)
+}
+
+#[test]
+fn tables_are_kept() {
+    let cache = test_cache("kept");
+    let table: Vec<u8> = (0..1000).map(|i| (i * 7 % 256) as u8).collect();
+    let builds = Cell::new(0);
+    let get = |version, len| {
+        let data = cache.get("test", version, len, || {
+            builds.set(builds.get() + 1);
+            table[..len].to_vec()
+        });
+        data.to_vec()
+    };
+
+    assert_eq!(get(1, 1000), table);
+    assert_eq!(get(1, 1000), table);
+    // Tables with a different version or length are stale
+    assert_eq!(get(2, 1000), table);
+    assert_eq!(get(2, 500), table[..500]);
+    assert_eq!(get(2, 500), table[..500]);
+    assert_eq!(builds.get(), 3);
+    let _ = fs::remove_dir_all(cache.dir());
+}
+
+#[test]
+fn corrupt_tables_are_rebuilt() {
+    let cache = test_cache("corrupt");
+    let table = vec![3; 100];
+    let path = cache.path("test");
+    let builds = Cell::new(0);
+    let get = || {
+        cache
+            .get("test", 1, 100, || {
+                builds.set(builds.get() + 1);
+                table.clone()
+            })
+            .to_vec()
+    };
+    assert_eq!(get(), table);
+
+    let damage: [fn(&mut Vec<u8>); 4] = [
+        // A flipped bit in the table
+        |file| file[HEADER_LEN + 50] ^= 1,
+        // A damaged header
+        |file| file[0] = b'X',
+        // A file that was cut short
+        |file| file.truncate(HEADER_LEN + 10),
+        |file| file.clear(),
+    ];
+    for damage in damage {
+        let mut file = fs::read(&path).unwrap();
+        damage(&mut file);
+        fs::write(&path, file).unwrap();
+        assert_eq!(get(), table);
+    }
+    // The rebuilt table was stored again
+    assert_eq!(get(), table);
+    assert_eq!(builds.get(), 5);
+
+    // A cache that can't be written to still gives out tables
+    fs::remove_dir_all(cache.dir()).unwrap();
+    fs::write(cache.dir(), "not a directory").unwrap();
+    assert_eq!(get(), table);
+    assert_eq!(builds.get(), 6);
+    fs::remove_file(cache.dir()).unwrap();
+}